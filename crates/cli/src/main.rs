@@ -1,13 +1,12 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::{Parser, ValueEnum};
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::path::PathBuf;
-use bindiff_core::{loader, arch::x86_64, analysis::hash::hash_function, diff::{matching::match_functions, DiffResult}};
-use serde_json;
+use bindiff_core::{loader, arch, analysis::hash::hash_function, diff::{matching::{match_functions_with, MatchOptions}, metadata::{diff_metadata, FieldChange}, DiffResult}};
 
 #[derive(Parser, Debug)]
-#[command(name = "bindiff", about = "Function-level binary diffing (x86_64, ELF & PE)")]
+#[command(name = "bindiff", about = "Function-level binary diffing (x86, x86_64, ARM, AArch64 & RISC-V, ELF, PE & Mach-O)")]
 struct Args {
     /// Old/left binary path
     a: PathBuf,
@@ -21,6 +20,92 @@ struct Args {
     /// Output HTML report to file
     #[arg(short='H', long)]
     out_html: Option<PathBuf>,
+
+    /// PDB file for a PE input; may be repeated, each is matched to A or B by CodeView GUID/age
+    #[arg(long)]
+    pdb: Vec<PathBuf>,
+
+    /// Directory to search for PDBs (flat or symbol-store layout) and ELF debug files
+    /// (by name or under .build-id/) before /usr/lib/debug; may be repeated
+    #[arg(long)]
+    symbol_path: Vec<PathBuf>,
+
+    /// Slice to diff from universal (fat) Mach-O inputs, e.g. arm64, arm64e or x86_64
+    /// (thin Mach-O inputs must match it); the instruction set of --raw inputs
+    #[arg(long)]
+    arch: Option<bindiff_core::TargetArch>,
+
+    /// Treat both inputs as flat images (firmware, ROM dumps) loaded at --base; needs --arch
+    #[arg(long, requires = "arch")]
+    raw: bool,
+
+    /// Load address of --raw inputs (hex)
+    #[arg(long, value_parser = parse_addr, default_value = "0", requires = "raw")]
+    base: u64,
+
+    /// Code address in --raw inputs (hex; bit 0 selects Thumb on ARM); may be repeated.
+    /// Defaults to --base
+    #[arg(long, value_parser = parse_addr, requires = "raw")]
+    entry: Vec<u64>,
+
+    /// Symbols for --raw inputs: CSV (name,address[,size]), nm output or a GNU ld map file
+    #[arg(long, requires = "raw")]
+    symbols: Option<PathBuf>,
+
+    /// Byte order of --raw inputs, e.g. little for mipsel or ppc64le. Defaults to the usual
+    /// one for --arch (big for MIPS, PowerPC and SPARC)
+    #[arg(long, value_enum, requires = "raw")]
+    endian: Option<EndianArg>,
+
+    /// Match Rust functions on their demangled path, ignoring symbol hashes that change
+    /// between builds
+    #[arg(long)]
+    match_rust_paths: bool,
+
+    /// Diff inputs built for different architectures or byte orders. Functions are still
+    /// matched by name, but nearly every one will show as modified
+    #[arg(long)]
+    allow_cross_arch: bool,
+
+    /// Disassembler engine (capstone requires the `capstone` feature)
+    #[arg(long, value_enum, default_value_t = DisassemblerArg::Auto)]
+    disassembler: DisassemblerArg,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum DisassemblerArg {
+    Auto,
+    Yaxpeax,
+    Capstone,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum EndianArg {
+    Little,
+    Big,
+}
+
+impl From<EndianArg> for bindiff_core::Endianness {
+    fn from(e: EndianArg) -> Self {
+        match e {
+            EndianArg::Little => Self::Little,
+            EndianArg::Big => Self::Big,
+        }
+    }
+}
+
+impl From<DisassemblerArg> for arch::Engine {
+    fn from(d: DisassemblerArg) -> Self {
+        match d {
+            DisassemblerArg::Auto => arch::Engine::Auto,
+            DisassemblerArg::Yaxpeax => arch::Engine::Native,
+            DisassemblerArg::Capstone => arch::Engine::Capstone,
+        }
+    }
+}
+
+fn parse_addr(s: &str) -> Result<u64, String> {
+    loader::raw::parse_addr(s).ok_or_else(|| format!("'{}' is not a hexadecimal address", s))
 }
 
 fn main() -> Result<()> {
@@ -29,19 +114,48 @@ fn main() -> Result<()> {
     pb.enable_steady_tick(std::time::Duration::from_millis(80));
     pb.set_style(ProgressStyle::with_template("{spinner:.green} {msg}")?);
     pb.set_message("Loading binaries...");
-    let bin_a = loader::load(&args.a)?;
-    let bin_b = loader::load(&args.b)?;
+    let opts = loader::LoadOptions {
+        pdb: args.pdb.clone(),
+        symbol_paths: args.symbol_path.clone(),
+        arch: args.arch,
+        raw: args.raw.then(|| loader::raw::RawOptions {
+            base: args.base,
+            entry_points: args.entry.clone(),
+            symbols: args.symbols.clone(),
+            endianness: args.endian.map(Into::into),
+        }),
+    };
+    let bin_a = loader::load_with(&args.a, &opts)?;
+    let bin_b = loader::load_with(&args.b, &opts)?;
+    let target = |bin: &loader::BinaryImage| {
+        let endian = match bin.endianness { bindiff_core::Endianness::Little => "little", bindiff_core::Endianness::Big => "big" };
+        format!("{} ({}-bit {}-endian, {} ABI)", bin.arch, bin.bits, endian, bin.abi)
+    };
+    if (bin_a.arch, bin_a.bits, bin_a.endianness) != (bin_b.arch, bin_b.bits, bin_b.endianness) {
+        if !args.allow_cross_arch {
+            pb.finish_and_clear();
+            bail!("{} is {} but {} is {}; pass --allow-cross-arch to diff them anyway",
+                args.a.display(), target(&bin_a), args.b.display(), target(&bin_b));
+        }
+    } else if bin_a.abi != bin_b.abi {
+        pb.suspend(|| eprintln!("{} {} uses the {} ABI but {} uses {}; calling sequences will differ",
+            "warning:".yellow().bold(), args.a.display(), bin_a.abi, args.b.display(), bin_b.abi));
+    }
+
+    let metadata = diff_metadata(&bin_a, &bin_b);
 
     pb.set_message("Disassembling functions...");
-    let fa = x86_64::disassemble_functions(&bin_a)?;
-    let fb = x86_64::disassemble_functions(&bin_b)?;
+    let engine = args.disassembler.into();
+    let fa = arch::disassemble(&bin_a, engine)?;
+    let fb = arch::disassemble(&bin_b, engine)?;
 
     pb.set_message("Hashing...");
     let ha: Vec<_> = fa.iter().map(hash_function).collect();
     let hb: Vec<_> = fb.iter().map(hash_function).collect();
 
     pb.set_message("Matching...");
-    let (added, removed, modified, unchanged) = match_functions(&fa, &fb, &ha, &hb);
+    let match_opts = MatchOptions { rust_paths: args.match_rust_paths };
+    let (added, removed, modified, unchanged) = match_functions_with(&fa, &fb, &ha, &hb, &match_opts);
     pb.finish_and_clear();
 
     println!("{}", "=== Summary ===".bold());
@@ -51,21 +165,55 @@ fn main() -> Result<()> {
     println!("  {} {}", "Removed:".red(), removed.len());
     println!();
 
-    for m in &modified {
-        let name = format!("{} -> {}", m.name_a.as_deref().unwrap_or("?"), m.name_b.as_deref().unwrap_or("?"));
-        println!("{} {}", "MOD".yellow().bold(), name);
+    let result = DiffResult { added, removed, modified, unchanged };
+    let members = result.member_summary();
+    if !members.is_empty() {
+        println!("{}", "=== Members ===".bold());
+        for (name, m) in &members {
+            println!("  {}: {} unchanged, {} modified, {} added, {} removed", name, m.unchanged, m.modified, m.added, m.removed);
+        }
+        println!();
     }
 
-    let result = DiffResult { added, removed, modified, unchanged };
+    if !metadata.is_empty() {
+        println!("{}", "=== Metadata ===".bold());
+        let field = |kind: &str, c: &FieldChange| {
+            println!("  {} {}: {} -> {}", kind, c.name, c.old.as_deref().unwrap_or("-"), c.new.as_deref().unwrap_or("-"));
+        };
+        metadata.headers.iter().for_each(|c| field("header", c));
+        metadata.hardening.iter().for_each(|c| field("hardening", c));
+        metadata.segments.iter().for_each(|c| field("segment", c));
+        for c in &metadata.sections {
+            let size = |v: Option<u64>, f: &Option<String>| match (v, f) {
+                (Some(v), Some(f)) if !f.is_empty() => format!("0x{:x} [{}]", v, f),
+                (Some(v), _) => format!("0x{:x}", v),
+                (None, _) => "-".to_string(),
+            };
+            println!("  section {}: {} -> {}", c.name, size(c.old_size, &c.old_flags), size(c.new_size, &c.new_flags));
+        }
+        for (kind, set) in [("library", &metadata.libraries), ("import", &metadata.imports)] {
+            set.added.iter().for_each(|n| println!("  {} {} {}", kind, "+".blue(), n));
+            set.removed.iter().for_each(|n| println!("  {} {} {}", kind, "-".red(), n));
+        }
+        println!();
+    }
+
+    for m in &result.modified {
+        let name = format!("{} -> {}", m.display_a().unwrap_or("?"), m.display_b().unwrap_or("?"));
+        match m.member() {
+            Some(member) => println!("{} [{}] {}", "MOD".yellow().bold(), member, name),
+            None => println!("{} {}", "MOD".yellow().bold(), name),
+        }
+    }
 
     if let Some(json_path) = args.out_json {
-        let jr: bindiff_report::JsonReport = result.clone().into();
+        let jr = bindiff_core::diff::report::JsonReport::from(result.clone()).with_metadata(metadata.clone());
         std::fs::write(&json_path, serde_json::to_string_pretty(&jr)?)?;
         println!("Wrote JSON report to {}", json_path.display());
     }
 
     if let Some(html_path) = args.out_html {
-        let html = bindiff_report::render_html(&result)?;
+        let html = bindiff_report::render_html_with(&result, Some(&metadata))?;
         std::fs::write(&html_path, html)?;
         println!("Wrote HTML report to {}", html_path.display());
     }
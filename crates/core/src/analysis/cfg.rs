@@ -1,35 +1,144 @@
-use crate::arch::FunctionIR;
+use crate::arch::{Flow, FunctionIR};
 use petgraph::graph::Graph;
 use petgraph::algo::is_isomorphic_matching;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::ops::Range;
 
-// Very lightweight basic block graph based only on RET boundaries.
-// This is a stub for future sophisticated CFG recovery.
-pub fn cfg_isomorphic(a: &FunctionIR, b: &FunctionIR) -> bool {
-    let to_blocks = |f: &FunctionIR| {
-        let mut blocks = vec![0usize];
-        for (i, insn) in f.insns.iter().enumerate() {
-            if insn.mnemonic.starts_with("RET") {
-                blocks.push(i + 1);
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// Block ends without a control transfer and runs into the next one.
+    Fallthrough,
+    /// Target of a jump, or of a conditional branch when taken.
+    Taken,
+    /// Conditional branch falling through.
+    NotTaken,
+    /// One entry of a resolved jump table.
+    Switch,
+    /// Block ends in a call; execution resumes after it.
+    CallReturn,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicBlock {
+    pub start: u64,
+    /// Indices into the function's `insns`.
+    pub insns: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// Basic-block graph of a single function. Blocks are in address order and
+/// edges refer to them by index.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ControlFlowGraph {
+    /// Block holding the function's first instruction; not necessarily the
+    /// lowest one when the function has fragments below its start.
+    pub entry: usize,
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+impl ControlFlowGraph {
+    pub fn build(f: &FunctionIR) -> Self {
+        let insns = &f.insns;
+        if insns.is_empty() {
+            return Self::default();
+        }
+        let index: HashMap<u64, usize> = insns.iter().enumerate().map(|(i, insn)| (insn.addr, i)).collect();
+        // Instructions `i` and `i + 1` are adjacent when the traversal reached
+        // both within one contiguous byte range.
+        let range_of = |addr: u64| f.coverage.partition_point(|r| r.1 <= addr);
+        let adjacent = |i: usize| i + 1 < insns.len() && range_of(insns[i].addr) == range_of(insns[i + 1].addr);
+        let target = |t: Option<u64>| t.and_then(|t| index.get(&t).copied());
+
+        let mut leader = vec![false; insns.len()];
+        leader[0] = true;
+        let entry = index.get(&f.start).copied().unwrap_or(0);
+        leader[entry] = true;
+        for (i, insn) in insns.iter().enumerate() {
+            if (!adjacent(i) || insn.flow != Flow::Next) && let Some(l) = leader.get_mut(i + 1) {
+                *l = true;
+            }
+            if let Flow::Branch(t) | Flow::Jump(t) = insn.flow && let Some(j) = target(t) {
+                leader[j] = true;
             }
         }
-        blocks
-    };
-    let ab = to_blocks(a);
-    let bb = to_blocks(b);
-    // Build chain graphs
-    let mut ga: Graph<usize, ()> = Graph::new();
-    let mut last = None;
-    for (i, _) in ab.iter().enumerate() {
-        let n = ga.add_node(i);
-        if let Some(l) = last { ga.add_edge(l, n, ()); }
-        last = Some(n);
+        for table in &f.jump_tables {
+            for &t in &table.targets {
+                if let Some(j) = target(Some(t)) { leader[j] = true; }
+            }
+        }
+
+        let mut blocks = Vec::new();
+        let mut block_of = vec![0usize; insns.len()];
+        for i in 0..insns.len() {
+            if leader[i] {
+                blocks.push(BasicBlock { start: insns[i].addr, insns: i..i + 1 });
+            } else if let Some(b) = blocks.last_mut() {
+                b.insns.end = i + 1;
+            }
+            block_of[i] = blocks.len() - 1;
+        }
+
+        let mut edges = Vec::new();
+        for (from, block) in blocks.iter().enumerate() {
+            let last = block.insns.end - 1;
+            let next = adjacent(last).then(|| block_of[last + 1]);
+            let mut edge = |to: Option<usize>, kind| {
+                if let Some(to) = to { edges.push(Edge { from, to, kind }); }
+            };
+            match insns[last].flow {
+                Flow::Next => edge(next, EdgeKind::Fallthrough),
+                Flow::Call(_) => edge(next, EdgeKind::CallReturn),
+                Flow::Branch(t) => {
+                    edge(target(t).map(|j| block_of[j]), EdgeKind::Taken);
+                    edge(next, EdgeKind::NotTaken);
+                }
+                Flow::Jump(None) => {
+                    let Some(table) = f.jump_tables.iter().find(|t| t.jump == insns[last].addr) else { continue };
+                    let mut cases: Vec<usize> = table.targets.iter().filter_map(|&t| target(Some(t))).map(|j| block_of[j]).collect();
+                    cases.sort_unstable();
+                    cases.dedup();
+                    for to in cases {
+                        edge(Some(to), EdgeKind::Switch);
+                    }
+                }
+                Flow::Jump(t) => edge(target(t).map(|j| block_of[j]), EdgeKind::Taken),
+                Flow::Return | Flow::Trap => {}
+            }
+        }
+        Self { entry: block_of[entry], blocks, edges }
+    }
+
+    pub fn successors(&self, block: usize) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |e| e.from == block)
     }
-    let mut gb: Graph<usize, ()> = Graph::new();
-    last = None;
-    for (i, _) in bb.iter().enumerate() {
-        let n = gb.add_node(i);
-        if let Some(l) = last { gb.add_edge(l, n, ()); }
-        last = Some(n);
+
+    pub fn predecessors(&self, block: usize) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |e| e.to == block)
+    }
+
+    /// The graph as a petgraph `Graph`, node weights being block start addresses.
+    pub fn to_graph(&self) -> Graph<u64, EdgeKind> {
+        let mut g = Graph::with_capacity(self.blocks.len(), self.edges.len());
+        let nodes: Vec<_> = self.blocks.iter().map(|b| g.add_node(b.start)).collect();
+        for e in &self.edges {
+            g.add_edge(nodes[e.from], nodes[e.to], e.kind);
+        }
+        g
     }
-    is_isomorphic_matching(&ga, &gb, |a, b| a == b, |_, _| true)
+}
+
+/// Whether two functions have the same block structure and edge kinds,
+/// ignoring addresses.
+pub fn cfg_isomorphic(a: &FunctionIR, b: &FunctionIR) -> bool {
+    let ga = ControlFlowGraph::build(a).to_graph();
+    let gb = ControlFlowGraph::build(b).to_graph();
+    is_isomorphic_matching(&ga, &gb, |_, _| true, |x, y| x == y)
 }
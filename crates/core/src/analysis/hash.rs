@@ -14,13 +14,14 @@ pub struct FunctionHash {
     pub n_insn: usize,
 }
 
-fn token_bytes(mnemonic: &str, op_kinds: &[crate::arch::OperandKind]) -> Vec<u8> {
+fn token_bytes(mnemonic: &str, op_kinds: &[crate::arch::OperandKind], callee: Option<&str>) -> Vec<u8> {
     let mut v = mnemonic.as_bytes().to_vec();
     for ok in op_kinds {
         let tag = match ok {
-            crate::arch::OperandKind::Reg(r) => {
-                // normalize register class, e.g., rax -> REG64
-                if r.starts_with('R') { b"REG64".to_vec() } else { b"REG".to_vec() }
+            crate::arch::OperandKind::Reg(_, class) => match class {
+                crate::arch::RegClass::Gpr(bits) => format!("REG{}", bits).into_bytes(),
+                crate::arch::RegClass::Float => b"FREG".to_vec(),
+                crate::arch::RegClass::Other => b"SREG".to_vec(),
             },
             crate::arch::OperandKind::Mem => b"MEM".to_vec(),
             crate::arch::OperandKind::Imm => b"IMM".to_vec(),
@@ -28,6 +29,11 @@ fn token_bytes(mnemonic: &str, op_kinds: &[crate::arch::OperandKind]) -> Vec<u8>
         };
         v.extend_from_slice(&tag);
     }
+    // Import names survive relinking, unlike the stub address.
+    if let Some(c) = callee {
+        v.extend_from_slice(b"->");
+        v.extend_from_slice(c.as_bytes());
+    }
     v
 }
 
@@ -42,16 +48,16 @@ fn u64_from_first8(bytes: &[u8]) -> u64 {
 pub fn simhash(tokens: &[Vec<u8>]) -> SimHash64 {
     let mut acc = [0i32; 64];
     for t in tokens {
-        let h = blake3::hash(&t).as_bytes().to_owned();
-        let mut val = u64_from_first8(&h);
-        for i in 0..64 {
+        let h = blake3::hash(t).as_bytes().to_owned();
+        let val = u64_from_first8(&h);
+        for (i, a) in acc.iter_mut().enumerate() {
             let bit = (val >> i) & 1;
-            acc[i] += if bit == 1 { 1 } else { -1 };
+            *a += if bit == 1 { 1 } else { -1 };
         }
     }
     let mut out = 0u64;
-    for i in 0..64 {
-        if acc[i] >= 0 { out |= 1 << i; }
+    for (i, a) in acc.iter().enumerate() {
+        if *a >= 0 { out |= 1 << i; }
     }
     SimHash64(out)
 }
@@ -60,7 +66,7 @@ pub fn hash_function(f: &FunctionIR) -> FunctionHash {
     let mut hasher = Hasher::new();
     let mut toks = Vec::with_capacity(f.insns.len());
     for insn in &f.insns {
-        let tb = token_bytes(&insn.mnemonic, &insn.op_kinds);
+        let tb = token_bytes(&insn.mnemonic, &insn.op_kinds, insn.callee.as_deref());
         hasher.update(&tb);
         toks.push(tb);
     }
@@ -0,0 +1,82 @@
+use super::{Flow, Insn, OperandKind, RegClass, Disassembler};
+use yaxpeax_arch::{Decoder, U8Reader};
+use yaxpeax_arm::armv8::a64::{InstDecoder, Instruction, Opcode, Operand, SizeCode};
+
+fn gpr(size: SizeCode, n: u16, sp: bool) -> OperandKind {
+    let name = match (size, n) {
+        (SizeCode::X, 31) => if sp { "SP".into() } else { "XZR".into() },
+        (SizeCode::W, 31) => if sp { "WSP".into() } else { "WZR".into() },
+        (SizeCode::X, n) => format!("X{}", n),
+        (SizeCode::W, n) => format!("W{}", n),
+    };
+    OperandKind::Reg(name, RegClass::Gpr(if size == SizeCode::X { 64 } else { 32 }))
+}
+
+fn normalize_operand(op: &Operand) -> Option<OperandKind> {
+    use OperandKind::*;
+    Some(match op {
+        Operand::Nothing => return None,
+        Operand::Register(sz, n) | Operand::RegisterPair(sz, n) | Operand::RegShift(_, _, sz, n) => gpr(*sz, *n, false),
+        Operand::RegisterOrSP(sz, n) => gpr(*sz, *n, true),
+        Operand::SIMDRegister(sz, n) => Reg(format!("{:?}{}", sz, n), RegClass::Float),
+        Operand::SIMDRegisterElements(_, n, _)
+        | Operand::SIMDRegisterElementsLane(_, n, _, _)
+        | Operand::SIMDRegisterElementsMultipleLane(_, n, _, _, _)
+        | Operand::SIMDRegisterGroup(_, n, _, _)
+        | Operand::SIMDRegisterGroupLane(n, _, _, _) => Reg(format!("V{}", n), RegClass::Float),
+        Operand::PCOffset(_) | Operand::Immediate(_) | Operand::Imm64(_) | Operand::Imm16(_)
+        | Operand::ImmediateDouble(_) | Operand::ImmShift(_, _) | Operand::ImmShiftMSL(_, _) => Imm,
+        Operand::RegRegOffset(..) | Operand::RegPreIndex(..) | Operand::RegPostIndex(..)
+        | Operand::RegPostIndexReg(..) => Mem,
+        _ => Other,
+    })
+}
+
+fn flow(addr: u64, insn: &Instruction) -> Flow {
+    // Branch offsets are relative to the branch itself.
+    let target = insn.operands.iter().find_map(|op| match op {
+        Operand::PCOffset(off) => Some(addr.wrapping_add(*off as u64)),
+        _ => None,
+    });
+    match insn.opcode {
+        Opcode::RET | Opcode::RETAA | Opcode::RETAB | Opcode::ERET | Opcode::ERETAA
+        | Opcode::ERETAB | Opcode::DRPS => Flow::Return,
+        Opcode::B => Flow::Jump(target),
+        Opcode::BR | Opcode::BRAA | Opcode::BRAAZ | Opcode::BRAB | Opcode::BRABZ => Flow::Jump(None),
+        Opcode::BL => Flow::Call(target),
+        Opcode::BLR | Opcode::BLRAA | Opcode::BLRAAZ | Opcode::BLRAB | Opcode::BLRABZ => Flow::Call(None),
+        Opcode::Bcc(_) | Opcode::CBZ | Opcode::CBNZ | Opcode::TBZ | Opcode::TBNZ => Flow::Branch(target),
+        Opcode::BRK | Opcode::HLT | Opcode::UDF => Flow::Trap,
+        _ => Flow::Next,
+    }
+}
+
+fn normalize_instruction(addr: u64, insn: &Instruction) -> Insn {
+    let mnemonic = format!("{:?}", insn.opcode);
+    let op_kinds = insn.operands.iter().filter_map(normalize_operand).collect();
+    Insn { addr, mnemonic, op_kinds, flow: flow(addr, insn), callee: None }
+}
+
+/// AArch64 (A64) backend on top of yaxpeax-arm. Every instruction is 4 bytes.
+#[derive(Default)]
+pub struct AArch64 {
+    dec: InstDecoder,
+}
+
+impl AArch64 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Disassembler for AArch64 {
+    fn name(&self) -> &'static str {
+        "yaxpeax-arm (a64)"
+    }
+
+    fn decode(&self, bytes: &[u8], addr: u64) -> Option<(Insn, usize)> {
+        let mut reader = U8Reader::new(bytes);
+        let insn = self.dec.decode(&mut reader).ok()?;
+        Some((normalize_instruction(addr, &insn), 4))
+    }
+}
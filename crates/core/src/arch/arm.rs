@@ -0,0 +1,214 @@
+use super::{Flow, Insn, OperandKind, RegClass, Disassembler};
+use crate::loader::{BinaryImage, MappingKind, MappingSymbol};
+use yaxpeax_arch::{Decoder, LengthedInstruction, U8Reader};
+use yaxpeax_arm::armv7::{ConditionCode, InstDecoder, Instruction, Opcode, Operand, Reg as ArmReg, RegShiftStyle};
+
+/// A core register; SP, LR and PC are as wide as the rest.
+fn reg(r: ArmReg) -> OperandKind {
+    let name = match r.number() {
+        13 => "SP".into(),
+        14 => "LR".into(),
+        15 => "PC".into(),
+        n => format!("R{}", n),
+    };
+    OperandKind::Reg(name, RegClass::Gpr(32))
+}
+
+fn normalize_operand(op: &Operand, out: &mut Vec<OperandKind>) {
+    use OperandKind::*;
+    match op {
+        Operand::Nothing => {},
+        Operand::Reg(r) | Operand::RegWBack(r, _) => out.push(reg(*r)),
+        Operand::RegList(list) => {
+            for n in 0..16u8 {
+                if list & (1 << n) != 0 { out.push(reg(ArmReg::from_u8(n))); }
+            }
+        },
+        Operand::RegShift(s) => {
+            let r = match s.into_shift() {
+                RegShiftStyle::RegImm(s) => s.shiftee(),
+                RegShiftStyle::RegReg(s) => s.shiftee(),
+            };
+            out.push(reg(r));
+        },
+        Operand::RegDeref(_)
+        | Operand::RegDerefPostindexRegShift(..) | Operand::RegDerefPreindexRegShift(..)
+        | Operand::RegDerefPostindexOffset(..) | Operand::RegDerefPreindexOffset(..)
+        | Operand::RegDerefPostindexReg(..) | Operand::RegDerefPreindexReg(..) => out.push(Mem),
+        Operand::Imm12(_) | Operand::Imm32(_) | Operand::BranchOffset(_) | Operand::BranchThumbOffset(_) => out.push(Imm),
+        _ => out.push(Other),
+    }
+}
+
+/// Halfwords yaxpeax-arm already added to the offset of the Thumb branch
+/// starting with halfword `hw`: it counts the 16-bit `B<cond>` (T1) and
+/// `CBZ`/`CBNZ` from the next instruction, but every other form from the
+/// Thumb PC (`addr + 4`) like the architecture does.
+fn thumb_folded(hw: u16, len: u64) -> i64 {
+    let folded = len == 2 && (hw >> 12 == 0xd || hw & 0xf500 == 0xb100);
+    folded as i64
+}
+
+/// `folded` is what `thumb_folded` says for Thumb code.
+fn flow(addr: u64, thumb: bool, folded: i64, insn: &Instruction) -> Flow {
+    // yaxpeax-arm pre-compensates A32 `B`/`BL` for the PC reading 8 ahead.
+    let target = insn.operands.iter().find_map(|op| match *op {
+        Operand::BranchOffset(imm) => Some(addr.wrapping_add((imm as i64 * 4) as u64)),
+        // Thumb `BLX` switches to ARM, relative to the word-aligned PC.
+        Operand::BranchThumbOffset(imm) if thumb && insn.opcode == Opcode::BLX => {
+            Some((addr.wrapping_add(4) & !3).wrapping_add((imm as i64 * 2) as u64))
+        }
+        Operand::BranchThumbOffset(imm) if thumb => Some(addr.wrapping_add(4).wrapping_add(((imm as i64 - folded) * 2) as u64)),
+        Operand::BranchThumbOffset(imm) => Some(addr.wrapping_add(8).wrapping_add((imm as i64 * 2) as u64)),
+        _ => None,
+    });
+    let writes_pc = match insn.operands[0] {
+        Operand::Reg(r) | Operand::RegWBack(r, _) => r.number() == 15,
+        Operand::RegList(list) => list & (1 << 15) != 0,
+        _ => false,
+    };
+    let loads_pc = |list_at: usize| matches!(insn.operands[list_at], Operand::RegList(list) if list & (1 << 15) != 0);
+    let lr = |op: &Operand| matches!(op, Operand::Reg(r) if r.number() == 14);
+    let flow = match insn.opcode {
+        Opcode::B => Flow::Jump(target),
+        Opcode::BL | Opcode::BLX => Flow::Call(target),
+        Opcode::BX if lr(&insn.operands[0]) => Flow::Return,
+        Opcode::BX | Opcode::BXJ | Opcode::TBB | Opcode::TBH => Flow::Jump(None),
+        Opcode::CBZ | Opcode::CBNZ => Flow::Branch(target),
+        Opcode::POP if writes_pc => Flow::Return,
+        Opcode::LDM(..) if loads_pc(1) => Flow::Return,
+        Opcode::MOV if writes_pc && lr(&insn.operands[1]) => Flow::Return,
+        Opcode::LDR | Opcode::MOV | Opcode::ADD if writes_pc => Flow::Jump(None),
+        Opcode::ERET | Opcode::RFE(..) => Flow::Return,
+        Opcode::UDF | Opcode::BKPT => Flow::Trap,
+        _ => Flow::Next,
+    };
+    // A conditional jump or return may fall through.
+    match flow {
+        Flow::Jump(t) if insn.condition != ConditionCode::AL => Flow::Branch(t),
+        Flow::Return if insn.condition != ConditionCode::AL => Flow::Branch(None),
+        flow => flow,
+    }
+}
+
+fn normalize_instruction(addr: u64, thumb: bool, folded: i64, insn: &Instruction) -> Insn {
+    // Keep the condition in the mnemonic: `bne` and `b` are different control flow.
+    let mnemonic = match insn.condition {
+        ConditionCode::AL => format!("{:?}", insn.opcode),
+        cond => format!("{:?}{:?}", insn.opcode, cond),
+    };
+    let mut op_kinds = Vec::new();
+    for op in &insn.operands {
+        normalize_operand(op, &mut op_kinds);
+    }
+    Insn { addr, mnemonic, op_kinds, flow: flow(addr, thumb, folded, insn), callee: None }
+}
+
+/// ARMv7 backend (A32 and Thumb-2) on top of yaxpeax-arm.
+///
+/// The instruction set at each address comes from the image's mapping
+/// symbols (`$a`/`$t`/`$d`) and the Thumb bit of ELF function symbols.
+/// Ranges marked `$d` are literal pools and are skipped rather than decoded.
+pub struct Arm {
+    arm: InstDecoder,
+    thumb: InstDecoder,
+    map: Vec<MappingSymbol>,
+}
+
+impl Arm {
+    pub fn new(bin: &BinaryImage) -> Self {
+        Self {
+            arm: InstDecoder::armv7(),
+            thumb: InstDecoder::armv7_thumb(),
+            map: bin.mapping_symbols.clone(),
+        }
+    }
+
+}
+
+/// Instruction set in effect at `addr`, and where the next mapping symbol starts.
+pub(crate) fn mode_at(map: &[MappingSymbol], addr: u64) -> (MappingKind, Option<u64>) {
+    let idx = map.partition_point(|m| m.addr <= addr);
+    let kind = if idx == 0 { MappingKind::Arm } else { map[idx - 1].kind };
+    (kind, map.get(idx).map(|m| m.addr))
+}
+
+impl Disassembler for Arm {
+    fn name(&self) -> &'static str {
+        "yaxpeax-arm (armv7/thumb2)"
+    }
+
+    fn decode(&self, bytes: &[u8], addr: u64) -> Option<(Insn, usize)> {
+        let (dec, thumb) = match mode_at(&self.map, addr).0 {
+            MappingKind::Arm => (&self.arm, false),
+            MappingKind::Thumb => (&self.thumb, true),
+            MappingKind::Data => return None,
+        };
+        let mut reader = U8Reader::new(bytes);
+        let insn = dec.decode(&mut reader).ok()?;
+        let len = insn.len().to_const() as u64;
+        let folded = if thumb { thumb_folded(u16::from_le_bytes([bytes[0], bytes[1]]), len) } else { 0 };
+        Some((normalize_instruction(addr, thumb, folded, &insn), len as usize))
+    }
+
+
+    fn data_end(&self, addr: u64) -> Option<u64> {
+        match mode_at(&self.map, addr) {
+            (MappingKind::Data, next) => Some(next.unwrap_or(u64::MAX)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(thumb: bool, bytes: &[u8], addr: u64) -> Insn {
+        let kind = if thumb { MappingKind::Thumb } else { MappingKind::Arm };
+        let arm = Arm { arm: InstDecoder::armv7(), thumb: InstDecoder::armv7_thumb(), map: vec![MappingSymbol { addr: 0, kind }] };
+        let (insn, len) = arm.decode(bytes, addr).unwrap();
+        assert_eq!(len, bytes.len());
+        insn
+    }
+
+    fn thumb(hws: &[u16], addr: u64) -> Flow {
+        let bytes: Vec<u8> = hws.iter().flat_map(|h| h.to_le_bytes()).collect();
+        decode(true, &bytes, addr).flow
+    }
+
+    fn arm(word: u32, addr: u64) -> Flow {
+        decode(false, &word.to_le_bytes(), addr).flow
+    }
+
+    #[test]
+    fn arm_branches() {
+        assert_eq!(arm(0xea00_0000, 0x1000), Flow::Jump(Some(0x1008)));   // b .+8
+        assert_eq!(arm(0xeaff_fffc, 0x1000), Flow::Jump(Some(0x0ff8)));   // b .-8
+        assert_eq!(arm(0x1a00_0002, 0x1000), Flow::Branch(Some(0x1010))); // bne .+16
+        assert_eq!(arm(0xeb00_003e, 0x1000), Flow::Call(Some(0x1100)));   // bl .+0x100
+        assert_eq!(arm(0xfb00_0000, 0x1000), Flow::Call(Some(0x100a)));   // blx .+10 (Thumb)
+        assert_eq!(arm(0xe12f_ff33, 0x1000), Flow::Call(None));           // blx r3
+    }
+
+    #[test]
+    fn thumb_16_bit_branches() {
+        assert_eq!(thumb(&[0xe7fe], 0x1000), Flow::Jump(Some(0x1000)));   // b.n .
+        assert_eq!(thumb(&[0xe000], 0x1000), Flow::Jump(Some(0x1004)));   // b.n .+4
+        assert_eq!(thumb(&[0xd1fe], 0x1000), Flow::Branch(Some(0x1000))); // bne.n .
+        assert_eq!(thumb(&[0xd100], 0x1000), Flow::Branch(Some(0x1004))); // bne.n .+4
+        assert_eq!(thumb(&[0xb100], 0x1000), Flow::Branch(Some(0x1004))); // cbz r0, .+4
+        assert_eq!(thumb(&[0xb108], 0x1000), Flow::Branch(Some(0x1006))); // cbz r0, .+6
+        assert_eq!(thumb(&[0xb908], 0x1000), Flow::Branch(Some(0x1006))); // cbnz r0, .+6
+    }
+
+    #[test]
+    fn thumb_32_bit_branches() {
+        assert_eq!(thumb(&[0xf000, 0xb880], 0x1000), Flow::Jump(Some(0x1104)));   // b.w .+0x104
+        assert_eq!(thumb(&[0xf000, 0x8080], 0x1000), Flow::Branch(Some(0x1104))); // beq.w .+0x104
+        assert_eq!(thumb(&[0xf000, 0xf880], 0x1000), Flow::Call(Some(0x1104)));   // bl .+0x104
+        assert_eq!(thumb(&[0xf7ff, 0xfffe], 0x1000), Flow::Call(Some(0x1000)));   // bl .
+        // blx to ARM code, from the word-aligned PC.
+        assert_eq!(thumb(&[0xf000, 0xe880], 0x1002), Flow::Call(Some(0x1104)));
+    }
+}
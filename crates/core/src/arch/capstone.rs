@@ -0,0 +1,414 @@
+use super::{Architecture, Endianness, Flow, Insn, OperandKind, RegClass, Disassembler, ArchError};
+use super::arm::mode_at;
+use crate::loader::{BinaryImage, MappingKind, MappingSymbol};
+use capstone::arch::{self, ArchOperand, BuildsCapstone, BuildsCapstoneEndian, BuildsCapstoneExtraMode};
+use capstone::{Capstone, Endian, InsnGroupType};
+use std::sync::Mutex;
+
+/// A capstone handle that may move between threads.
+struct Handle(Capstone);
+
+// SAFETY: a capstone handle is not thread-safe, but it is not tied to the
+// thread that created it either; every use goes through the `Mutex` below.
+unsafe impl Send for Handle {}
+
+fn cs_endian(e: Endianness) -> Endian {
+    match e {
+        Endianness::Little => Endian::Little,
+        Endianness::Big => Endian::Big,
+    }
+}
+
+fn build(arch: Architecture, endian: Endianness) -> capstone::CsResult<Capstone> {
+    let e = cs_endian(endian);
+    match arch {
+        Architecture::X86 => Capstone::new().x86().mode(arch::x86::ArchMode::Mode32).detail(true).build(),
+        Architecture::X86_64 => Capstone::new().x86().mode(arch::x86::ArchMode::Mode64).detail(true).build(),
+        Architecture::AArch64 => Capstone::new().arm64().mode(arch::arm64::ArchMode::Arm).endian(e).detail(true).build(),
+        Architecture::Arm => Capstone::new().arm().mode(arch::arm::ArchMode::Arm).endian(e).detail(true).build(),
+        Architecture::Mips => Capstone::new().mips().mode(arch::mips::ArchMode::Mips32).endian(e).detail(true).build(),
+        Architecture::Mips64 => Capstone::new().mips().mode(arch::mips::ArchMode::Mips64).endian(e).detail(true).build(),
+        Architecture::PowerPc => Capstone::new().ppc().mode(arch::ppc::ArchMode::Mode32).endian(e).detail(true).build(),
+        Architecture::PowerPc64 => Capstone::new().ppc().mode(arch::ppc::ArchMode::Mode64).endian(e).detail(true).build(),
+        Architecture::Sparc => Capstone::new().sparc().mode(arch::sparc::ArchMode::Default).detail(true).build(),
+        Architecture::Sparc64 => Capstone::new().sparc().mode(arch::sparc::ArchMode::V9).detail(true).build(),
+        Architecture::S390x => Capstone::new().sysz().mode(arch::sysz::ArchMode::Default).detail(true).build(),
+        Architecture::RiscV32 | Architecture::RiscV64 => {
+            let mode = if arch == Architecture::RiscV64 { arch::riscv::ArchMode::RiscV64 } else { arch::riscv::ArchMode::RiscV32 };
+            Capstone::new().riscv().mode(mode).extra_mode([arch::riscv::ArchExtraMode::RiscVC].into_iter()).detail(true).build()
+        },
+        Architecture::Unknown(_) => Err(capstone::Error::UnsupportedArch),
+    }
+}
+
+/// Class of the capstone register `name` (upper-cased) on `arch`.
+fn reg_class(arch: Architecture, name: &str) -> RegClass {
+    use Architecture::*;
+    let numbered = |p: &str| name.strip_prefix(p).is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+    let any_numbered = |ps: &[&str]| ps.iter().any(|p| numbered(p));
+    match arch {
+        X86 | X86_64 => {
+            if name.starts_with("ST(") || any_numbered(&["XMM", "YMM", "ZMM", "MM", "FP"]) {
+                RegClass::Float
+            } else if any_numbered(&["CR", "DR", "K", "BND"])
+                || matches!(name, "CS" | "DS" | "ES" | "FS" | "GS" | "SS" | "IP" | "EIP" | "RIP" | "FLAGS" | "EFLAGS" | "RFLAGS" | "FPSW")
+            {
+                RegClass::Other
+            } else if name.starts_with('R') && name[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                // R8..R15 with their D/W/B halves
+                RegClass::Gpr(match name.as_bytes()[name.len() - 1] {
+                    b'D' => 32,
+                    b'W' => 16,
+                    b'B' => 8,
+                    _ => 64,
+                })
+            } else if name.starts_with('R') {
+                RegClass::Gpr(64)
+            } else if name.starts_with('E') {
+                RegClass::Gpr(32)
+            } else if (name.len() == 2 && (name.ends_with('L') || name.ends_with('H'))) || matches!(name, "SPL" | "BPL" | "SIL" | "DIL") {
+                RegClass::Gpr(8)
+            } else {
+                RegClass::Gpr(16)
+            }
+        },
+        AArch64 => {
+            if numbered("X") || matches!(name, "SP" | "XZR" | "FP" | "LR") {
+                RegClass::Gpr(64)
+            } else if numbered("W") || matches!(name, "WSP" | "WZR") {
+                RegClass::Gpr(32)
+            } else if any_numbered(&["B", "H", "S", "D", "Q", "V", "Z", "P"]) {
+                RegClass::Float
+            } else {
+                RegClass::Other
+            }
+        },
+        Arm => {
+            if numbered("R") || matches!(name, "SB" | "SL" | "FP" | "IP" | "SP" | "LR" | "PC") {
+                RegClass::Gpr(32)
+            } else if any_numbered(&["S", "D", "Q"]) {
+                RegClass::Float
+            } else {
+                RegClass::Other
+            }
+        },
+        Mips | Mips64 | PowerPc | PowerPc64 | Sparc | Sparc64 | S390x | RiscV32 | RiscV64 | Unknown(_) => {
+            let bits = if matches!(arch, Mips64 | PowerPc64 | Sparc64 | S390x | RiscV64) { 64 } else { 32 };
+            let float = match arch {
+                Mips | Mips64 => any_numbered(&["F", "$F", "W", "$W"]),
+                PowerPc | PowerPc64 => any_numbered(&["F", "V", "VS"]),
+                RiscV32 | RiscV64 => name.starts_with('F') && name != "FP",
+                _ => numbered("F"),
+            };
+            let other = match arch {
+                Mips | Mips64 => matches!(name, "HI" | "LO" | "PC"),
+                PowerPc | PowerPc64 => numbered("CR") || matches!(name, "LR" | "CTR" | "XER" | "CARRY"),
+                Sparc | Sparc64 => numbered("FCC") || matches!(name, "ICC" | "XCC" | "Y"),
+                S390x => numbered("A"),
+                _ => false,
+            };
+            if float { RegClass::Float } else if other { RegClass::Other } else { RegClass::Gpr(bits) }
+        },
+    }
+}
+
+fn normalize_operand(arch: Architecture, cs: &Capstone, op: &ArchOperand) -> OperandKind {
+    use OperandKind::*;
+    let reg = |r| {
+        let name = cs.reg_name(r).unwrap_or_default().to_uppercase();
+        let class = reg_class(arch, &name);
+        Reg(name, class)
+    };
+    match op {
+        ArchOperand::X86Operand(o) => match o.op_type {
+            arch::x86::X86OperandType::Reg(r) => reg(r),
+            arch::x86::X86OperandType::Imm(_) => Imm,
+            arch::x86::X86OperandType::Mem(_) => Mem,
+            _ => Other,
+        },
+        ArchOperand::ArmOperand(o) => match o.op_type {
+            arch::arm::ArmOperandType::Reg(r) => reg(r),
+            arch::arm::ArmOperandType::Imm(_) | arch::arm::ArmOperandType::Cimm(_)
+            | arch::arm::ArmOperandType::Pimm(_) | arch::arm::ArmOperandType::Fp(_) => Imm,
+            arch::arm::ArmOperandType::Mem(_) => Mem,
+            _ => Other,
+        },
+        ArchOperand::Arm64Operand(o) => match o.op_type {
+            arch::arm64::Arm64OperandType::Reg(r) => reg(r),
+            arch::arm64::Arm64OperandType::Imm(_) | arch::arm64::Arm64OperandType::Cimm(_)
+            | arch::arm64::Arm64OperandType::Fp(_) => Imm,
+            arch::arm64::Arm64OperandType::Mem(_) => Mem,
+            _ => Other,
+        },
+        ArchOperand::MipsOperand(o) => match o {
+            arch::mips::MipsOperand::Reg(r) => reg(*r),
+            arch::mips::MipsOperand::Imm(_) => Imm,
+            arch::mips::MipsOperand::Mem(_) => Mem,
+            _ => Other,
+        },
+        ArchOperand::PpcOperand(o) => match o {
+            arch::ppc::PpcOperand::Reg(r) => reg(*r),
+            arch::ppc::PpcOperand::Imm(_) => Imm,
+            arch::ppc::PpcOperand::Mem(_) => Mem,
+            _ => Other,
+        },
+        ArchOperand::SparcOperand(o) => match o {
+            arch::sparc::SparcOperand::Reg(r) => reg(*r),
+            arch::sparc::SparcOperand::Imm(_) => Imm,
+            arch::sparc::SparcOperand::Mem(_) => Mem,
+            _ => Other,
+        },
+        ArchOperand::SysZOperand(o) => match o {
+            arch::sysz::SysZOperand::Reg(r) | arch::sysz::SysZOperand::AcReg(r) => reg(*r),
+            arch::sysz::SysZOperand::Imm(_) => Imm,
+            arch::sysz::SysZOperand::Mem(_) => Mem,
+            _ => Other,
+        },
+        ArchOperand::RiscVOperand(o) => match o {
+            arch::riscv::RiscVOperand::Reg(r) => reg(*r),
+            arch::riscv::RiscVOperand::Imm(_) => Imm,
+            arch::riscv::RiscVOperand::Mem(_) => Mem,
+            _ => Other,
+        },
+        _ => Other,
+    }
+}
+
+/// Immediate operand value; capstone already resolves branch immediates to
+/// absolute addresses. A branch target is the last immediate: PowerPC
+/// `bc BO,BI,target` and AArch64 `tbz Rt,#bit,target` come with others first.
+fn imm_value(op: &ArchOperand) -> Option<u64> {
+    Some(match op {
+        ArchOperand::X86Operand(o) => match o.op_type { arch::x86::X86OperandType::Imm(v) => v as u64, _ => return None },
+        ArchOperand::ArmOperand(o) => match o.op_type { arch::arm::ArmOperandType::Imm(v) => v as u32 as u64, _ => return None },
+        ArchOperand::Arm64Operand(o) => match o.op_type { arch::arm64::Arm64OperandType::Imm(v) => v as u64, _ => return None },
+        ArchOperand::MipsOperand(arch::mips::MipsOperand::Imm(v))
+        | ArchOperand::PpcOperand(arch::ppc::PpcOperand::Imm(v))
+        | ArchOperand::SparcOperand(arch::sparc::SparcOperand::Imm(v))
+        | ArchOperand::SysZOperand(arch::sysz::SysZOperand::Imm(v))
+        | ArchOperand::RiscVOperand(arch::riscv::RiscVOperand::Imm(v)) => *v as u64,
+        _ => return None,
+    })
+}
+
+/// Returns capstone does not put in its `ret` group.
+fn is_return(arch: Architecture, m: &str, has: impl Fn(&str) -> bool) -> bool {
+    match arch {
+        Architecture::Arm => (m == "BX" && has("LR")) || (matches!(m, "POP" | "LDM") && has("PC")),
+        Architecture::Mips | Architecture::Mips64 => matches!(m, "JR" | "JRC") && has("RA"),
+        Architecture::PowerPc | Architecture::PowerPc64 => matches!(m, "BLR" | "RFI" | "RFID"),
+        Architecture::Sparc | Architecture::Sparc64 => matches!(m, "RET" | "RETL" | "RETT"),
+        Architecture::S390x => m == "BR" && has("R14"),
+        Architecture::RiscV32 | Architecture::RiscV64 => matches!(m, "JR" | "C.JR") && has("RA"),
+        _ => false,
+    }
+}
+
+/// Calls capstone leaves out of its `call` group. `word` is the instruction
+/// as a big- or little-endian word, as the image stores it.
+fn is_call(arch: Architecture, m: &str, word: u32) -> bool {
+    match arch {
+        Architecture::Mips | Architecture::Mips64 => {
+            matches!(m, "JAL" | "JALR" | "JALX" | "JALRC" | "BAL" | "BGEZAL" | "BLTZAL" | "BALC" | "JIALC") || m.ends_with("ALC")
+        },
+        // `b`, `bc`, `bclr` and `bcctr` with the link bit set, however
+        // capstone spells the condition (`bnl` is "not less", `bnll` links).
+        Architecture::PowerPc | Architecture::PowerPc64 => matches!(word >> 26, 16 | 18 | 19) && word & 1 != 0,
+        Architecture::Sparc | Architecture::Sparc64 => m == "CALL",
+        _ => false,
+    }
+}
+
+/// Jumps that never fall through; capstone's `jump` group mixes them with
+/// conditional branches.
+fn is_unconditional(arch: Architecture, m: &str) -> bool {
+    match arch {
+        Architecture::X86 | Architecture::X86_64 => matches!(m, "JMP" | "LJMP"),
+        Architecture::AArch64 => matches!(m, "B" | "BR" | "BRAA" | "BRAAZ" | "BRAB" | "BRABZ"),
+        Architecture::Arm => matches!(m, "B" | "BX" | "B.W" | "TBB" | "TBH"),
+        Architecture::Mips | Architecture::Mips64 => matches!(m, "J" | "JR" | "B" | "BC" | "JRC"),
+        Architecture::PowerPc | Architecture::PowerPc64 => matches!(m, "B" | "BA" | "BCTR"),
+        Architecture::Sparc | Architecture::Sparc64 => matches!(m, "BA" | "JMP" | "JMPL"),
+        Architecture::S390x => matches!(m, "J" | "JG" | "BR"),
+        Architecture::RiscV32 | Architecture::RiscV64 => matches!(m, "J" | "JR" | "C.J" | "C.JR"),
+        Architecture::Unknown(_) => false,
+    }
+}
+
+fn decode_with(arch: Architecture, endian: Endianness, cs: &Capstone, bytes: &[u8], addr: u64) -> Option<(Insn, usize)> {
+    let insns = cs.disasm_count(bytes, addr, 1).ok()?;
+    let insn = insns.iter().next()?;
+    let detail = cs.insn_detail(insn).ok()?;
+    let mnemonic = insn.mnemonic()?.to_uppercase();
+    let operands = detail.arch_detail().operands();
+    let op_kinds: Vec<_> = operands.iter().map(|op| normalize_operand(arch, cs, op)).collect();
+
+    let in_group = |g: InsnGroupType::Type| detail.groups().iter().any(|id| id.0 as InsnGroupType::Type == g);
+    let has = |r: &str| op_kinds.iter().any(|k| matches!(k, OperandKind::Reg(n, _) if n == r));
+    let target = operands.iter().rev().find_map(imm_value);
+    let word = insn.bytes().get(..4).and_then(|b| b.try_into().ok()).map_or(0, |w| match endian {
+        Endianness::Little => u32::from_le_bytes(w),
+        Endianness::Big => u32::from_be_bytes(w),
+    });
+    // SPARC spells the annul bit and prediction hints as suffixes (`ba,a`).
+    let m = mnemonic.split(',').next().unwrap_or_default();
+    let flow = if in_group(InsnGroupType::CS_GRP_RET) || in_group(InsnGroupType::CS_GRP_IRET) || is_return(arch, m, has) {
+        Flow::Return
+    } else if in_group(InsnGroupType::CS_GRP_CALL) || is_call(arch, m, word) {
+        Flow::Call(target)
+    } else if in_group(InsnGroupType::CS_GRP_JUMP) {
+        if is_unconditional(arch, m) { Flow::Jump(target) } else { Flow::Branch(target) }
+    } else if matches!(m, "UD2" | "HLT" | "BRK" | "UDF" | "BKPT" | "EBREAK" | "C.EBREAK") {
+        Flow::Trap
+    } else if arch == Architecture::Arm && matches!(m, "LDR" | "MOV") && op_kinds.first().is_some_and(|k| matches!(k, OperandKind::Reg(n, _) if n == "PC")) {
+        Flow::Jump(None)
+    } else {
+        Flow::Next
+    };
+    Some((Insn { addr, mnemonic, op_kinds, flow, callee: None }, insn.len()))
+}
+
+/// Capstone-based backend covering every ISA in `Architecture`, including
+/// ones yaxpeax has no decoder for (MIPS, PowerPC, SPARC, s390x).
+///
+/// Mnemonics and register names are Capstone's, so its output only compares
+/// meaningfully against other Capstone output.
+pub struct CapstoneBackend {
+    arch: Architecture,
+    endian: Endianness,
+    cs: Mutex<Handle>,
+    /// Second handle for Thumb code on 32-bit ARM.
+    thumb: Option<Mutex<Handle>>,
+    map: Vec<MappingSymbol>,
+}
+
+impl CapstoneBackend {
+    pub fn new(bin: &BinaryImage) -> Result<Self, ArchError> {
+        let init = |e: capstone::Error| ArchError::Init("capstone", e.to_string());
+        let cs = build(bin.arch, bin.endianness).map_err(init)?;
+        let thumb = if bin.arch == Architecture::Arm {
+            let t = Capstone::new().arm().mode(arch::arm::ArchMode::Thumb)
+                .endian(cs_endian(bin.endianness)).detail(true).build().map_err(init)?;
+            Some(Mutex::new(Handle(t)))
+        } else {
+            None
+        };
+        Ok(Self { arch: bin.arch, endian: bin.endianness, cs: Mutex::new(Handle(cs)), thumb, map: bin.mapping_symbols.clone() })
+    }
+}
+
+impl Disassembler for CapstoneBackend {
+    fn name(&self) -> &'static str {
+        "capstone"
+    }
+
+    fn decode(&self, bytes: &[u8], addr: u64) -> Option<(Insn, usize)> {
+        let handle = match (mode_at(&self.map, addr).0, &self.thumb) {
+            (MappingKind::Data, _) => return None,
+            (MappingKind::Thumb, Some(t)) => t,
+            _ => &self.cs,
+        };
+        let guard = handle.lock().ok()?;
+        decode_with(self.arch, self.endian, &guard.0, bytes, addr)
+    }
+
+
+    fn has_delay_slot(&self, insn: &Insn) -> bool {
+        let m = insn.mnemonic.as_str();
+        match self.arch {
+            // MIPS R6 and microMIPS compact branches (`bc`, `beqzc`, `jic`,
+            // `jialc`, `jrc`, ...) end in `C` and have no slot.
+            Architecture::Mips | Architecture::Mips64 => {
+                !matches!(m, "ERET" | "ERETNC" | "DERET") && !(m.ends_with('C') && (m.starts_with('B') || m.starts_with('J')))
+            },
+            // An annulled unconditional branch (`ba,a`) skips its slot; an
+            // annulled conditional one still runs it when taken.
+            Architecture::Sparc | Architecture::Sparc64 => {
+                let mut parts = m.split(',');
+                let base = parts.next().unwrap_or_default();
+                !(parts.any(|p| p == "A") && matches!(base, "BA" | "FBA" | "CBA" | "BN" | "FBN" | "CBN"))
+            },
+            _ => false,
+        }
+    }
+
+    fn data_end(&self, addr: u64) -> Option<u64> {
+        match mode_at(&self.map, addr) {
+            (MappingKind::Data, next) => Some(next.unwrap_or(u64::MAX)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend(arch: Architecture, endian: Endianness) -> CapstoneBackend {
+        let cs = build(arch, endian).unwrap();
+        CapstoneBackend { arch, endian, cs: Mutex::new(Handle(cs)), thumb: None, map: Vec::new() }
+    }
+
+    /// Decodes the big-endian `word` at 0x1000; also says whether it has a
+    /// delay slot.
+    fn decode(dis: &CapstoneBackend, word: u32) -> (Flow, bool) {
+        let (insn, len) = dis.decode(&word.to_be_bytes(), 0x1000).unwrap();
+        assert_eq!(len, 4);
+        (insn.flow, insn.flow != Flow::Next && dis.has_delay_slot(&insn))
+    }
+
+    #[test]
+    fn mips_branches() {
+        let mips = backend(Architecture::Mips, Endianness::Big);
+        assert_eq!(decode(&mips, 0x1085_0003), (Flow::Branch(Some(0x1010)), true)); // beq a0, a1, .+16
+        assert_eq!(decode(&mips, 0x0800_0410), (Flow::Jump(Some(0x1040)), true));   // j 0x1040
+        assert_eq!(decode(&mips, 0x0c00_0410), (Flow::Call(Some(0x1040)), true));   // jal 0x1040
+        assert_eq!(decode(&mips, 0x0411_0003), (Flow::Call(Some(0x1010)), true));   // bal .+16
+        assert_eq!(decode(&mips, 0x03e0_0008), (Flow::Return, true));               // jr ra
+    }
+
+    #[test]
+    fn mips_r6_compact_branches_have_no_delay_slot() {
+        let cs = Capstone::new().mips().mode(arch::mips::ArchMode::Mips32R6).endian(Endian::Big).detail(true).build().unwrap();
+        let r6 = CapstoneBackend { cs: Mutex::new(Handle(cs)), ..backend(Architecture::Mips, Endianness::Big) };
+        // bc, balc, beqzc a0, bnezc a0, jic t9, jialc t9
+        for word in [0xc800_0003, 0xe800_0003, 0xd880_0003, 0xf880_0003, 0xd819_0000, 0xf819_0000] {
+            let (insn, _) = r6.decode(&u32::to_be_bytes(word), 0x1000).unwrap();
+            assert!(!r6.has_delay_slot(&insn), "{}", insn.mnemonic);
+        }
+        assert!(decode(&r6, 0x1085_0003).1); // beq a0, a1, .+16
+    }
+
+    #[test]
+    fn ppc_branches() {
+        let ppc = backend(Architecture::PowerPc, Endianness::Big);
+        assert_eq!(decode(&ppc, 0x4182_0010), (Flow::Branch(Some(0x1010)), false)); // beq .+16
+        assert_eq!(decode(&ppc, 0x4086_0008), (Flow::Branch(Some(0x1008)), false)); // bne cr1, .+8
+        assert_eq!(decode(&ppc, 0x4800_0010), (Flow::Jump(Some(0x1010)), false));   // b .+16
+        assert_eq!(decode(&ppc, 0x4800_0011), (Flow::Call(Some(0x1010)), false));   // bl .+16
+        assert_eq!(decode(&ppc, 0x4080_0008).0, Flow::Branch(Some(0x1008)));        // bnl .+8
+        assert_eq!(decode(&ppc, 0x4080_0009).0, Flow::Call(Some(0x1008)));          // bnll .+8
+        assert_eq!(decode(&ppc, 0x4e80_0421).0, Flow::Call(None));                  // bctrl
+        assert_eq!(decode(&ppc, 0x4e80_0020).0, Flow::Return);                      // blr
+    }
+
+    #[test]
+    fn sparc_branches() {
+        let sparc = backend(Architecture::Sparc, Endianness::Big);
+        assert_eq!(decode(&sparc, 0x1080_0004), (Flow::Jump(Some(0x1010)), true));   // ba .+16
+        assert_eq!(decode(&sparc, 0x3080_0004), (Flow::Jump(Some(0x1010)), false));  // ba,a .+16
+        assert_eq!(decode(&sparc, 0x1280_0004), (Flow::Branch(Some(0x1010)), true)); // bne .+16
+        assert_eq!(decode(&sparc, 0x3280_0004), (Flow::Branch(Some(0x1010)), true)); // bne,a .+16
+        assert_eq!(decode(&sparc, 0x4000_0004), (Flow::Call(Some(0x1010)), true));   // call .+16
+        let v9 = backend(Architecture::Sparc64, Endianness::Big);
+        assert_eq!(decode(&v9, 0x3248_0004), (Flow::Branch(Some(0x1010)), true));    // bne,a,pt %icc, .+16
+    }
+
+    #[test]
+    fn branch_target_is_the_last_immediate() {
+        let a64 = backend(Architecture::AArch64, Endianness::Little);
+        // tbz x0, #3, .+16
+        let (insn, _) = a64.decode(&0x3618_0080u32.to_le_bytes(), 0x1000).unwrap();
+        assert_eq!(insn.flow, Flow::Branch(Some(0x1010)));
+    }
+}
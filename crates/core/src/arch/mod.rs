@@ -1,20 +1,303 @@
+pub mod x86;
 pub mod x86_64;
+pub mod aarch64;
+pub mod arm;
+pub mod riscv;
+#[cfg(feature = "capstone")]
+pub mod capstone;
+pub mod registry;
 
+use crate::loader::kernel::{PatchKind, PatchSite};
+use crate::loader::{BinaryImage, DebugInfo, FunctionSymbol, synthetic_name};
+use anyhow::Result;
 use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+pub use registry::{ArchError, Engine, backend_for, disassemble};
+
+/// Instruction set of a loaded image, derived from the ELF `e_machine`,
+/// COFF `Machine` or Mach-O `cputype` header field.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Architecture {
+    X86,
+    X86_64,
+    AArch64,
+    Arm,
+    RiscV32,
+    RiscV64,
+    Mips,
+    Mips64,
+    PowerPc,
+    PowerPc64,
+    Sparc,
+    Sparc64,
+    S390x,
+    /// Header value we have no name for.
+    Unknown(u32),
+}
+
+impl Architecture {
+    pub fn from_elf_machine(e_machine: u16, is_64: bool) -> Self {
+        use goblin::elf::header::*;
+        match e_machine {
+            EM_386 => Self::X86,
+            EM_X86_64 => Self::X86_64,
+            EM_AARCH64 => Self::AArch64,
+            EM_ARM => Self::Arm,
+            EM_RISCV if is_64 => Self::RiscV64,
+            EM_RISCV => Self::RiscV32,
+            EM_MIPS | EM_MIPS_RS3_LE if is_64 => Self::Mips64,
+            EM_MIPS | EM_MIPS_RS3_LE => Self::Mips,
+            EM_PPC => Self::PowerPc,
+            EM_PPC64 => Self::PowerPc64,
+            EM_SPARC | EM_SPARC32PLUS => Self::Sparc,
+            EM_SPARCV9 => Self::Sparc64,
+            EM_S390 => Self::S390x,
+            other => Self::Unknown(other as u32),
+        }
+    }
+
+    pub fn from_coff_machine(machine: u16) -> Self {
+        use goblin::pe::header::*;
+        match machine {
+            COFF_MACHINE_X86 => Self::X86,
+            COFF_MACHINE_X86_64 => Self::X86_64,
+            COFF_MACHINE_ARM64 => Self::AArch64,
+            COFF_MACHINE_ARM | COFF_MACHINE_ARMNT | COFF_MACHINE_THUMB => Self::Arm,
+            COFF_MACHINE_RISCV32 => Self::RiscV32,
+            COFF_MACHINE_RISCV64 => Self::RiscV64,
+            COFF_MACHINE_R4000 | COFF_MACHINE_WCEMIPSV2 | COFF_MACHINE_MIPS16
+            | COFF_MACHINE_MIPSFPU | COFF_MACHINE_MIPSFPU16 => Self::Mips,
+            COFF_MACHINE_POWERPC | COFF_MACHINE_POWERPCFP => Self::PowerPc,
+            other => Self::Unknown(other as u32),
+        }
+    }
+
+    pub fn from_macho_cputype(cputype: u32) -> Self {
+        use goblin::mach::cputype::*;
+        match cputype {
+            CPU_TYPE_X86 => Self::X86,
+            CPU_TYPE_X86_64 => Self::X86_64,
+            // arm64_32 (watchOS) is AArch64 code with 32-bit pointers.
+            CPU_TYPE_ARM64 | CPU_TYPE_ARM64_32 => Self::AArch64,
+            CPU_TYPE_ARM => Self::Arm,
+            CPU_TYPE_POWERPC => Self::PowerPc,
+            CPU_TYPE_POWERPC64 => Self::PowerPc64,
+            CPU_TYPE_SPARC => Self::Sparc,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// Parses the `Display` names plus the common aliases used by Apple
+/// (`arm64`, `i386`, `armv7`) and GNU (`amd64`, `ppc64`) tools.
+impl std::str::FromStr for Architecture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "x86" | "i386" | "i686" => Self::X86,
+            "x86_64" | "x86-64" | "amd64" | "x64" => Self::X86_64,
+            "aarch64" | "arm64" | "arm64_32" => Self::AArch64,
+            "arm" | "armv7" | "armv7s" | "armv7k" | "thumb" => Self::Arm,
+            "riscv32" => Self::RiscV32,
+            "riscv64" => Self::RiscV64,
+            "mips" => Self::Mips,
+            "mips64" => Self::Mips64,
+            "powerpc" | "ppc" => Self::PowerPc,
+            "powerpc64" | "ppc64" => Self::PowerPc64,
+            "sparc" => Self::Sparc,
+            "sparc64" | "sparcv9" => Self::Sparc64,
+            "s390x" => Self::S390x,
+            _ => return Err(format!("unknown architecture '{}'", s)),
+        })
+    }
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::X86 => f.write_str("x86"),
+            Self::X86_64 => f.write_str("x86_64"),
+            Self::AArch64 => f.write_str("aarch64"),
+            Self::Arm => f.write_str("arm"),
+            Self::RiscV32 => f.write_str("riscv32"),
+            Self::RiscV64 => f.write_str("riscv64"),
+            Self::Mips => f.write_str("mips"),
+            Self::Mips64 => f.write_str("mips64"),
+            Self::PowerPc => f.write_str("powerpc"),
+            Self::PowerPc64 => f.write_str("powerpc64"),
+            Self::Sparc => f.write_str("sparc"),
+            Self::Sparc64 => f.write_str("sparc64"),
+            Self::S390x => f.write_str("s390x"),
+            Self::Unknown(m) => write!(f, "unknown(0x{:x})", m),
+        }
+    }
+}
+
+/// An `--arch` value: an instruction set, or one of the Mach-O variants that
+/// share one with another slice of a universal binary (`arm64e`, `x86_64h`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TargetArch {
+    pub arch: Architecture,
+    /// Mach-O `cpusubtype` of the variant, without the capability bits.
+    pub subtype: Option<u32>,
+}
+
+impl TargetArch {
+    /// What a Mach-O slice with these header fields is selected by.
+    pub fn from_macho(cputype: u32, cpusubtype: u32) -> Self {
+        use goblin::mach::cputype::*;
+        let subtype = cpusubtype & !CPU_SUBTYPE_MASK;
+        let variant = matches!((cputype, subtype), (CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_E) | (CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_H));
+        Self { arch: Architecture::from_macho_cputype(cputype), subtype: variant.then_some(subtype) }
+    }
+}
+
+impl From<Architecture> for TargetArch {
+    fn from(arch: Architecture) -> Self {
+        Self { arch, subtype: None }
+    }
+}
+
+impl std::str::FromStr for TargetArch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use goblin::mach::cputype::{CPU_SUBTYPE_ARM64_E, CPU_SUBTYPE_X86_64_H};
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "arm64e" => Self { arch: Architecture::AArch64, subtype: Some(CPU_SUBTYPE_ARM64_E) },
+            "x86_64h" => Self { arch: Architecture::X86_64, subtype: Some(CPU_SUBTYPE_X86_64_H) },
+            _ => s.parse::<Architecture>()?.into(),
+        })
+    }
+}
+
+impl fmt::Display for TargetArch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use goblin::mach::cputype::{CPU_SUBTYPE_ARM64_E, CPU_SUBTYPE_X86_64_H};
+        match (self.arch, self.subtype) {
+            (_, None) => write!(f, "{}", self.arch),
+            (Architecture::AArch64, Some(CPU_SUBTYPE_ARM64_E)) => f.write_str("arm64e"),
+            (Architecture::X86_64, Some(CPU_SUBTYPE_X86_64_H)) => f.write_str("x86_64h"),
+            (arch, Some(subtype)) => write!(f, "{} (subtype {})", arch, subtype),
+        }
+    }
+}
+
+/// Calling convention family, from the file format and header flags. Code
+/// for two ABIs of one architecture passes arguments in different places.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Abi {
+    /// The architecture's ELF psABI (System V, AAPCS); also assumed for raw
+    /// images.
+    #[default]
+    SysV,
+    /// 32-bit pointers on a 64-bit instruction set (x32, AArch64 ILP32).
+    Ilp32,
+    /// 32-bit ARM with floating-point arguments in VFP registers (`armhf`).
+    ArmHardFloat,
+    Windows,
+    Darwin,
+}
+
+impl Abi {
+    pub fn from_elf(arch: Architecture, is_64: bool, e_flags: u32) -> Self {
+        const EF_ARM_ABI_FLOAT_HARD: u32 = 0x400;
+        match arch {
+            Architecture::X86_64 | Architecture::AArch64 if !is_64 => Self::Ilp32,
+            Architecture::Arm if e_flags & EF_ARM_ABI_FLOAT_HARD != 0 => Self::ArmHardFloat,
+            _ => Self::SysV,
+        }
+    }
+}
+
+impl fmt::Display for Abi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::SysV => "sysv",
+            Self::Ilp32 => "ilp32",
+            Self::ArmHardFloat => "armhf",
+            Self::Windows => "windows",
+            Self::Darwin => "darwin",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl fmt::Display for Endianness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Little => "little",
+            Self::Big => "big",
+        })
+    }
+}
+
+/// What a register holds, as the backend knows it; hashing keeps the class
+/// and drops which register the allocator picked.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum RegClass {
+    /// General-purpose register of this many bits, including stack pointer,
+    /// link register and (on ARM) PC.
+    Gpr(u8),
+    /// Floating-point or vector register.
+    Float,
+    /// Flags, segment, control, instruction pointer and other registers with
+    /// a fixed role.
+    Other,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum OperandKind {
-    Reg(String),
+    Reg(String, RegClass),
     Mem,      // normalized memory operand
     Imm,      // immediate literal normalized
     Other,
 }
 
+/// How control leaves an instruction. Branch targets are absolute addresses,
+/// `None` when the target is computed at run time.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Flow {
+    /// Falls through to the next instruction.
+    #[default]
+    Next,
+    /// Conditional branch: either the target or the next instruction.
+    Branch(Option<u64>),
+    /// Unconditional jump; never falls through.
+    Jump(Option<u64>),
+    /// Call; execution is assumed to resume at the next instruction.
+    Call(Option<u64>),
+    Return,
+    /// Trap, halt or undefined instruction; execution does not continue.
+    Trap,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Insn {
     pub addr: u64,
     pub mnemonic: String,
     pub op_kinds: Vec<OperandKind>,
+    pub flow: Flow,
+    /// Import a call or jump reaches, e.g. `puts` through its PLT stub.
+    #[serde(default)]
+    pub callee: Option<String>,
+}
+
+/// A resolved `switch` dispatch: the indirect jump, the table it reads and
+/// the case targets in table order.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct JumpTable {
+    pub jump: u64,
+    pub table: u64,
+    pub targets: Vec<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,5 +305,230 @@ pub struct FunctionIR {
     pub name: String,
     pub start: u64,
     pub size: u64,
+    /// Instructions reachable from `start`, in address order.
     pub insns: Vec<Insn>,
+    /// Half-open `[start, end)` byte ranges actually reached by the traversal.
+    pub coverage: Vec<(u64, u64)>,
+    pub jump_tables: Vec<JumpTable>,
+    #[serde(default)]
+    pub debug: Option<DebugInfo>,
+    /// Archive member holding the function, for archive inputs.
+    #[serde(default)]
+    pub member: Option<String>,
+    #[serde(default)]
+    pub demangled: Option<String>,
+}
+
+/// An instruction-set backend that turns a loaded image into normalized `FunctionIR`.
+///
+/// Backends only have to decode single instructions and classify their
+/// control flow; walking function bodies is shared so every ISA bounds
+/// functions the same way.
+pub trait Disassembler: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Decode the instruction at the start of `bytes`, which lives at `addr`.
+    /// Returns the normalized instruction and its encoded length.
+    fn decode(&self, bytes: &[u8], addr: u64) -> Option<(Insn, usize)>;
+
+    /// Whether the instruction after `insn` executes before its control
+    /// transfer takes effect (MIPS/SPARC branch delay slots).
+    fn has_delay_slot(&self, _insn: &Insn) -> bool {
+        false
+    }
+
+    /// If `addr` starts data embedded in code (e.g. an ARM literal pool), the
+    /// address where instructions resume.
+    fn data_end(&self, _addr: u64) -> Option<u64> {
+        None
+    }
+
+    /// Resolve the indirect jump at `addr` as a jump table. `path` holds the
+    /// addresses of the instructions decoded on the way there, oldest first.
+    fn jump_table(&self, _bin: &BinaryImage, _path: &[u64], _addr: u64) -> Option<JumpTable> {
+        None
+    }
+
+    /// Name of the import the call or jump `insn` reaches. By default only
+    /// direct branches to a PLT stub or `extern` slot resolve.
+    fn import(&self, bin: &BinaryImage, insn: &Insn) -> Option<String> {
+        match insn.flow {
+            Flow::Call(Some(t)) | Flow::Jump(Some(t)) => bin.imports.get(&t).cloned(),
+            _ => None,
+        }
+    }
+
+    fn disassemble(&self, bin: &BinaryImage) -> Result<Vec<FunctionIR>> {
+        Ok(recover_functions(self, bin))
+    }
+}
+
+/// Longest encoding any backend decodes (x86 caps instructions at 15 bytes).
+const MAX_INSN_LEN: usize = 16;
+
+/// Recursive descent from each known function start.
+///
+/// Conditional branches queue both successors, calls continue at the return
+/// address, and jumps are only followed while they stay inside the function:
+/// a jump to another known function start or out of the body is a tail call.
+/// Indirect jumps are followed when the backend resolves them as a jump table.
+/// Without a symbol size the body ends at the next known function start or
+/// the end of the segment; detached fragments (`.cold` parts) belong to the
+/// body as well.
+///
+/// Direct call targets that are not known functions yet become `sub_<addr>`
+/// functions; bodies whose bound moved because of them are walked again until
+/// nothing new turns up.
+pub fn recover_functions<D: Disassembler + ?Sized>(dis: &D, bin: &BinaryImage) -> Vec<FunctionIR> {
+    let mut funcs = bin.functions.clone();
+    // Per function: the body end it was last recovered with, and the result.
+    let mut done: Vec<Option<(u64, Option<FunctionIR>)>> = vec![None; funcs.len()];
+    loop {
+        let mut starts: Vec<u64> = funcs.iter().map(|f| f.start).collect();
+        starts.sort_unstable();
+        starts.dedup();
+        let mut found = BTreeSet::new();
+        for (i, f) in funcs.iter().enumerate() {
+            let end = body_end(bin, f.start, f.size, &starts);
+            if matches!(done[i], Some((e, _)) if e == end) { continue; }
+            let ir = recover_function(dis, bin, f, end, &starts);
+            if let Some(ir) = &ir {
+                let own = |t: u64| ir.coverage.iter().any(|r| t >= r.0 && t < r.1);
+                found.extend(ir.insns.iter()
+                    .filter_map(|insn| match insn.flow { Flow::Call(t) => t, _ => None })
+                    .filter(|&t| bin.is_executable(t) && !own(t) && starts.binary_search(&t).is_err())
+                    // PLT stubs are not functions of the image.
+                    .filter(|t| !bin.imports.contains_key(t)));
+            }
+            done[i] = Some((end, ir));
+        }
+        if found.is_empty() { break; }
+        for t in found {
+            funcs.push(FunctionSymbol { name: synthetic_name(t), start: t, size: 0, fragments: Vec::new(), debug: None, demangled: None });
+            done.push(None);
+        }
+    }
+    done.into_iter().filter_map(|d| d.and_then(|(_, ir)| ir)).collect()
+}
+
+fn body_end(bin: &BinaryImage, start: u64, size: u64, starts: &[u64]) -> u64 {
+    if size > 0 {
+        return start.saturating_add(size);
+    }
+    let seg_end = bin.segment_at(start).map_or(start, |s| s.vaddr + s.vsize);
+    let next = starts.get(starts.partition_point(|&s| s <= start)).copied();
+    next.map_or(seg_end, |n| n.min(seg_end))
+}
+
+/// Collapse a runtime-patched kernel site into one marker instruction, so
+/// the build-time placeholder (NOP, jump, `call __fentry__`) does not count.
+/// A static key keeps its out-of-line branch as a conditional target.
+fn patched(insn: Insn, len: usize, site: &PatchSite, limit: usize) -> (Insn, usize) {
+    let (mnemonic, flow) = match site.kind {
+        PatchKind::Alternative => ("alternative", Flow::Next),
+        PatchKind::StaticKey { target } => ("static_key", Flow::Branch(Some(target))),
+        PatchKind::Ftrace => ("ftrace", Flow::Next),
+    };
+    let len = if site.len > 0 { (site.len as usize).min(limit) } else { len };
+    (Insn { addr: insn.addr, mnemonic: mnemonic.to_string(), op_kinds: Vec::new(), flow, callee: None }, len)
+}
+
+fn recover_function<D: Disassembler + ?Sized>(
+    dis: &D,
+    bin: &BinaryImage,
+    f: &FunctionSymbol,
+    end: u64,
+    starts: &[u64],
+) -> Option<FunctionIR> {
+    let start = f.start;
+    // End of the body part (main range or detached fragment) holding `a`.
+    let part_end = |a: u64| {
+        std::iter::once((start, end)).chain(f.fragments.iter().copied())
+            .find(|&(s, e)| a >= s && a < e)
+            .map(|(_, e)| e)
+    };
+    let inside = |a: u64| part_end(a).is_some() && (a == start || starts.binary_search(&a).is_err());
+
+    let mut seen: BTreeMap<u64, (Insn, usize)> = BTreeMap::new();
+    let mut jump_tables = Vec::new();
+    // (address, decode only this instruction): the latter for delay slots.
+    let mut work = vec![(start, false)];
+    while let Some((mut addr, last)) = work.pop() {
+        let mut path = Vec::new();
+        while inside(addr) && !seen.contains_key(&addr) {
+            if let Some(resume) = dis.data_end(addr) {
+                if resume <= addr || last { break; }
+                addr = resume;
+                continue;
+            }
+            let limit = part_end(addr).map_or(0, |e| e - addr) as usize;
+            let Some(bytes) = bin.read_va(addr, MAX_INSN_LEN.min(limit)) else { break };
+            let Some((mut insn, mut len)) = dis.decode(bytes, addr) else { break };
+            if len == 0 { break; }
+            if let Some(site) = bin.patch_site(addr) {
+                (insn, len) = patched(insn, len, site, limit);
+            }
+            if matches!(insn.flow, Flow::Call(_) | Flow::Jump(_)) && !bin.imports.is_empty() {
+                insn.callee = dis.import(bin, &insn);
+            }
+            let flow = insn.flow;
+            let slot = flow != Flow::Next && dis.has_delay_slot(&insn);
+            seen.insert(addr, (insn, len));
+            let next = addr + len as u64;
+            if last { break; }
+            match flow {
+                Flow::Next | Flow::Call(_) => {}
+                Flow::Branch(target) => {
+                    if let Some(t) = target.filter(|&t| inside(t)) {
+                        work.push((t, false));
+                    }
+                }
+                Flow::Jump(None) => {
+                    if let Some(table) = dis.jump_table(bin, &path, addr) {
+                        work.extend(table.targets.iter().filter(|&&t| inside(t)).map(|&t| (t, false)));
+                        jump_tables.push(table);
+                    }
+                    if slot { work.push((next, true)); }
+                    break;
+                }
+                Flow::Jump(Some(t)) => {
+                    if inside(t) {
+                        work.push((t, false));
+                    }
+                    if slot { work.push((next, true)); }
+                    break;
+                }
+                Flow::Return | Flow::Trap => {
+                    if slot { work.push((next, true)); }
+                    break;
+                }
+            }
+            path.push(addr);
+            addr = next;
+        }
+    }
+    if seen.is_empty() {
+        return None;
+    }
+
+    let mut coverage: Vec<(u64, u64)> = Vec::new();
+    for (&addr, &(_, len)) in &seen {
+        let stop = addr + len as u64;
+        match coverage.last_mut() {
+            Some(r) if r.1 >= addr => r.1 = r.1.max(stop),
+            _ => coverage.push((addr, stop)),
+        }
+    }
+    let extent = coverage.iter().filter(|r| r.0 >= start && r.0 < end).map(|r| r.1 - start).max().unwrap_or(0);
+    Some(FunctionIR {
+        name: f.name.clone(),
+        start,
+        size: if f.size > 0 { f.size } else { extent },
+        insns: seen.into_values().map(|(insn, _)| insn).collect(),
+        coverage,
+        jump_tables,
+        debug: f.debug.clone(),
+        member: bin.segment_at(start).and_then(|s| s.member.clone()),
+        demangled: f.demangled.clone(),
+    })
 }
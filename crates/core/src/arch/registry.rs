@@ -0,0 +1,69 @@
+use super::{Architecture, Disassembler, FunctionIR};
+use crate::loader::BinaryImage;
+use anyhow::Result;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ArchError {
+    #[error("no disassembler backend for architecture {0} ({1}-bit)")]
+    Unsupported(Architecture, u8),
+    #[error("the {0} disassembler is not available in this build")]
+    EngineUnavailable(&'static str),
+    #[error("failed to initialize {0} backend: {1}")]
+    Init(&'static str, String),
+}
+
+/// Which family of decoders to use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// The native backend for the architecture, falling back to Capstone when
+    /// there is none and the `capstone` feature is enabled.
+    #[default]
+    Auto,
+    /// Pure-Rust decoders only (yaxpeax and the built-in RISC-V decoder).
+    Native,
+    /// Capstone, if compiled in with the `capstone` feature.
+    Capstone,
+}
+
+fn native_backend(bin: &BinaryImage) -> Option<Box<dyn Disassembler>> {
+    match bin.arch {
+        Architecture::X86 => Some(Box::new(super::x86::X86::new())),
+        Architecture::X86_64 => Some(Box::new(super::x86_64::X86_64::new())),
+        Architecture::AArch64 => Some(Box::new(super::aarch64::AArch64::new())),
+        Architecture::Arm => Some(Box::new(super::arm::Arm::new(bin))),
+        Architecture::RiscV32 => Some(Box::new(super::riscv::RiscV::new(false))),
+        Architecture::RiscV64 => Some(Box::new(super::riscv::RiscV::new(true))),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "capstone")]
+fn capstone_backend(bin: &BinaryImage) -> Result<Box<dyn Disassembler>, ArchError> {
+    match bin.arch {
+        Architecture::Unknown(_) => Err(ArchError::Unsupported(bin.arch, bin.bits)),
+        _ => Ok(Box::new(super::capstone::CapstoneBackend::new(bin)?)),
+    }
+}
+
+#[cfg(not(feature = "capstone"))]
+fn capstone_backend(_bin: &BinaryImage) -> Result<Box<dyn Disassembler>, ArchError> {
+    Err(ArchError::EngineUnavailable("capstone"))
+}
+
+/// Pick the backend matching the image's header-derived architecture.
+pub fn backend_for(bin: &BinaryImage, engine: Engine) -> Result<Box<dyn Disassembler>, ArchError> {
+    match engine {
+        Engine::Native => native_backend(bin).ok_or(ArchError::Unsupported(bin.arch, bin.bits)),
+        Engine::Capstone => capstone_backend(bin),
+        Engine::Auto => match native_backend(bin) {
+            Some(b) => Ok(b),
+            None if cfg!(feature = "capstone") => capstone_backend(bin),
+            None => Err(ArchError::Unsupported(bin.arch, bin.bits)),
+        },
+    }
+}
+
+pub fn disassemble(bin: &BinaryImage, engine: Engine) -> Result<Vec<FunctionIR>> {
+    backend_for(bin, engine)?.disassemble(bin)
+}
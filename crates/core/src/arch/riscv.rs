@@ -0,0 +1,495 @@
+use super::{Flow, Insn, OperandKind, RegClass, Disassembler};
+
+const XREGS: [&str; 32] = [
+    "ZERO", "RA", "SP", "GP", "TP", "T0", "T1", "T2", "S0", "S1", "A0", "A1", "A2", "A3", "A4", "A5",
+    "A6", "A7", "S2", "S3", "S4", "S5", "S6", "S7", "S8", "S9", "S10", "S11", "T3", "T4", "T5", "T6",
+];
+
+const FREGS: [&str; 32] = [
+    "FT0", "FT1", "FT2", "FT3", "FT4", "FT5", "FT6", "FT7", "FS0", "FS1", "FA0", "FA1", "FA2", "FA3", "FA4", "FA5",
+    "FA6", "FA7", "FS2", "FS3", "FS4", "FS5", "FS6", "FS7", "FS8", "FS9", "FS10", "FS11", "FT8", "FT9", "FT10", "FT11",
+];
+
+/// An integer register, as wide as on RV64; `RiscV::decode` narrows the
+/// class for RV32.
+fn x(n: u32) -> OperandKind {
+    OperandKind::Reg(XREGS[(n & 31) as usize].to_string(), RegClass::Gpr(64))
+}
+
+fn f(n: u32) -> OperandKind {
+    OperandKind::Reg(FREGS[(n & 31) as usize].to_string(), RegClass::Float)
+}
+
+/// Registers `x8`..`x15` addressed by the 3-bit fields of compressed encodings.
+fn xc(n: u32) -> OperandKind {
+    x(8 + (n & 7))
+}
+
+fn fc(n: u32) -> OperandKind {
+    f(8 + (n & 7))
+}
+
+use OperandKind::{Imm, Mem, Other};
+
+type Decoded = (&'static str, Vec<OperandKind>);
+
+fn decode32(w: u32, rv64: bool) -> Option<Decoded> {
+    let rd = (w >> 7) & 31;
+    let rs1 = (w >> 15) & 31;
+    let rs2 = (w >> 20) & 31;
+    let rs3 = w >> 27;
+    let funct3 = (w >> 12) & 7;
+    let funct7 = w >> 25;
+    Some(match w & 0x7f {
+        0x37 => ("LUI", vec![x(rd), Imm]),
+        0x17 => ("AUIPC", vec![x(rd), Imm]),
+        0x6f => ("JAL", vec![x(rd), Imm]),
+        0x67 if funct3 == 0 => ("JALR", vec![x(rd), x(rs1), Imm]),
+        0x63 => {
+            let m = match funct3 { 0 => "BEQ", 1 => "BNE", 4 => "BLT", 5 => "BGE", 6 => "BLTU", 7 => "BGEU", _ => return None };
+            (m, vec![x(rs1), x(rs2), Imm])
+        },
+        0x03 => {
+            let m = match funct3 {
+                0 => "LB", 1 => "LH", 2 => "LW", 4 => "LBU", 5 => "LHU",
+                3 if rv64 => "LD", 6 if rv64 => "LWU",
+                _ => return None,
+            };
+            (m, vec![x(rd), Mem])
+        },
+        0x23 => {
+            let m = match funct3 { 0 => "SB", 1 => "SH", 2 => "SW", 3 if rv64 => "SD", _ => return None };
+            (m, vec![x(rs2), Mem])
+        },
+        0x13 => {
+            let m = match (funct3, w >> 26) {
+                (0, _) => "ADDI", (2, _) => "SLTI", (3, _) => "SLTIU", (4, _) => "XORI", (6, _) => "ORI", (7, _) => "ANDI",
+                (1, 0) => "SLLI", (5, 0) => "SRLI", (5, 0x10) => "SRAI",
+                _ => return None,
+            };
+            (m, vec![x(rd), x(rs1), Imm])
+        },
+        0x1b if rv64 => {
+            let m = match (funct3, funct7) { (0, _) => "ADDIW", (1, 0) => "SLLIW", (5, 0) => "SRLIW", (5, 0x20) => "SRAIW", _ => return None };
+            (m, vec![x(rd), x(rs1), Imm])
+        },
+        0x33 => {
+            let m = match (funct7, funct3) {
+                (0, 0) => "ADD", (0x20, 0) => "SUB", (0, 1) => "SLL", (0, 2) => "SLT", (0, 3) => "SLTU",
+                (0, 4) => "XOR", (0, 5) => "SRL", (0x20, 5) => "SRA", (0, 6) => "OR", (0, 7) => "AND",
+                (1, 0) => "MUL", (1, 1) => "MULH", (1, 2) => "MULHSU", (1, 3) => "MULHU",
+                (1, 4) => "DIV", (1, 5) => "DIVU", (1, 6) => "REM", (1, 7) => "REMU",
+                _ => return None,
+            };
+            (m, vec![x(rd), x(rs1), x(rs2)])
+        },
+        0x3b if rv64 => {
+            let m = match (funct7, funct3) {
+                (0, 0) => "ADDW", (0x20, 0) => "SUBW", (0, 1) => "SLLW", (0, 5) => "SRLW", (0x20, 5) => "SRAW",
+                (1, 0) => "MULW", (1, 4) => "DIVW", (1, 5) => "DIVUW", (1, 6) => "REMW", (1, 7) => "REMUW",
+                _ => return None,
+            };
+            (m, vec![x(rd), x(rs1), x(rs2)])
+        },
+        0x0f => match funct3 { 0 => ("FENCE", vec![]), 1 => ("FENCE.I", vec![]), _ => return None },
+        0x73 => match (funct3, w >> 7) {
+            (0, 0) => ("ECALL", vec![]),
+            (0, 0x2000) => ("EBREAK", vec![]),
+            (0, 0x0604000) => ("MRET", vec![]),
+            (0, 0x0204000) => ("SRET", vec![]),
+            (0, 0x020a000) => ("WFI", vec![]),
+            (0, _) if funct7 == 0x09 => ("SFENCE.VMA", vec![x(rs1), x(rs2)]),
+            (1, _) => ("CSRRW", vec![x(rd), Other, x(rs1)]),
+            (2, _) => ("CSRRS", vec![x(rd), Other, x(rs1)]),
+            (3, _) => ("CSRRC", vec![x(rd), Other, x(rs1)]),
+            (5, _) => ("CSRRWI", vec![x(rd), Other, Imm]),
+            (6, _) => ("CSRRSI", vec![x(rd), Other, Imm]),
+            (7, _) => ("CSRRCI", vec![x(rd), Other, Imm]),
+            _ => return None,
+        },
+        0x2f => {
+            let d = match funct3 { 2 => false, 3 if rv64 => true, _ => return None };
+            let m = match (w >> 27, d) {
+                (0x02, false) => "LR.W", (0x02, true) => "LR.D",
+                (0x03, false) => "SC.W", (0x03, true) => "SC.D",
+                (0x01, false) => "AMOSWAP.W", (0x01, true) => "AMOSWAP.D",
+                (0x00, false) => "AMOADD.W", (0x00, true) => "AMOADD.D",
+                (0x04, false) => "AMOXOR.W", (0x04, true) => "AMOXOR.D",
+                (0x0c, false) => "AMOAND.W", (0x0c, true) => "AMOAND.D",
+                (0x08, false) => "AMOOR.W", (0x08, true) => "AMOOR.D",
+                (0x10, false) => "AMOMIN.W", (0x10, true) => "AMOMIN.D",
+                (0x14, false) => "AMOMAX.W", (0x14, true) => "AMOMAX.D",
+                (0x18, false) => "AMOMINU.W", (0x18, true) => "AMOMINU.D",
+                (0x1c, false) => "AMOMAXU.W", (0x1c, true) => "AMOMAXU.D",
+                _ => return None,
+            };
+            if w >> 27 == 0x02 { (m, vec![x(rd), Mem]) } else { (m, vec![x(rd), x(rs2), Mem]) }
+        },
+        0x07 => match funct3 { 2 => ("FLW", vec![f(rd), Mem]), 3 => ("FLD", vec![f(rd), Mem]), _ => return None },
+        0x27 => match funct3 { 2 => ("FSW", vec![f(rs2), Mem]), 3 => ("FSD", vec![f(rs2), Mem]), _ => return None },
+        op @ (0x43 | 0x47 | 0x4b | 0x4f) => {
+            let m = match (op, funct7 & 3) {
+                (0x43, 0) => "FMADD.S", (0x43, 1) => "FMADD.D",
+                (0x47, 0) => "FMSUB.S", (0x47, 1) => "FMSUB.D",
+                (0x4b, 0) => "FNMSUB.S", (0x4b, 1) => "FNMSUB.D",
+                (0x4f, 0) => "FNMADD.S", (0x4f, 1) => "FNMADD.D",
+                _ => return None,
+            };
+            (m, vec![f(rd), f(rs1), f(rs2), f(rs3)])
+        },
+        0x53 => decode_fp(funct7, funct3, rd, rs1, rs2)?,
+        _ => return None,
+    })
+}
+
+fn decode_fp(funct7: u32, funct3: u32, rd: u32, rs1: u32, rs2: u32) -> Option<Decoded> {
+    let dbl = match funct7 & 3 { 0 => false, 1 => true, _ => return None };
+    let pick = |s: &'static str, d: &'static str| if dbl { d } else { s };
+    Some(match funct7 >> 2 {
+        0x00 => (pick("FADD.S", "FADD.D"), vec![f(rd), f(rs1), f(rs2)]),
+        0x01 => (pick("FSUB.S", "FSUB.D"), vec![f(rd), f(rs1), f(rs2)]),
+        0x02 => (pick("FMUL.S", "FMUL.D"), vec![f(rd), f(rs1), f(rs2)]),
+        0x03 => (pick("FDIV.S", "FDIV.D"), vec![f(rd), f(rs1), f(rs2)]),
+        0x0b => (pick("FSQRT.S", "FSQRT.D"), vec![f(rd), f(rs1)]),
+        0x04 => {
+            let m = match funct3 { 0 => pick("FSGNJ.S", "FSGNJ.D"), 1 => pick("FSGNJN.S", "FSGNJN.D"), 2 => pick("FSGNJX.S", "FSGNJX.D"), _ => return None };
+            (m, vec![f(rd), f(rs1), f(rs2)])
+        },
+        0x05 => {
+            let m = match funct3 { 0 => pick("FMIN.S", "FMIN.D"), 1 => pick("FMAX.S", "FMAX.D"), _ => return None };
+            (m, vec![f(rd), f(rs1), f(rs2)])
+        },
+        0x08 => (pick("FCVT.S.D", "FCVT.D.S"), vec![f(rd), f(rs1)]),
+        0x14 => {
+            let m = match funct3 { 0 => pick("FLE.S", "FLE.D"), 1 => pick("FLT.S", "FLT.D"), 2 => pick("FEQ.S", "FEQ.D"), _ => return None };
+            (m, vec![x(rd), f(rs1), f(rs2)])
+        },
+        0x18 => {
+            let m = match rs2 {
+                0 => pick("FCVT.W.S", "FCVT.W.D"), 1 => pick("FCVT.WU.S", "FCVT.WU.D"),
+                2 => pick("FCVT.L.S", "FCVT.L.D"), 3 => pick("FCVT.LU.S", "FCVT.LU.D"),
+                _ => return None,
+            };
+            (m, vec![x(rd), f(rs1)])
+        },
+        0x1a => {
+            let m = match rs2 {
+                0 => pick("FCVT.S.W", "FCVT.D.W"), 1 => pick("FCVT.S.WU", "FCVT.D.WU"),
+                2 => pick("FCVT.S.L", "FCVT.D.L"), 3 => pick("FCVT.S.LU", "FCVT.D.LU"),
+                _ => return None,
+            };
+            (m, vec![f(rd), x(rs1)])
+        },
+        0x1c => match funct3 {
+            0 => (pick("FMV.X.W", "FMV.X.D"), vec![x(rd), f(rs1)]),
+            1 => (pick("FCLASS.S", "FCLASS.D"), vec![x(rd), f(rs1)]),
+            _ => return None,
+        },
+        0x1e if funct3 == 0 => (pick("FMV.W.X", "FMV.D.X"), vec![f(rd), x(rs1)]),
+        _ => return None,
+    })
+}
+
+/// Expand a compressed (C extension) instruction to the base instruction it
+/// stands for, so `c.addi` and `addi` normalize identically.
+fn decode16(h: u32, rv64: bool) -> Option<Decoded> {
+    let funct3 = h >> 13;
+    let rd = (h >> 7) & 31;
+    let rs2 = (h >> 2) & 31;
+    let rdp = (h >> 2) & 7;
+    let rs1p = (h >> 7) & 7;
+    let bit12 = (h >> 12) & 1;
+    Some(match (h & 3, funct3) {
+        (0, 0) if h == 0 => return None,
+        (0, 0) => ("ADDI", vec![xc(rdp), x(2), Imm]),
+        (0, 1) => ("FLD", vec![fc(rdp), Mem]),
+        (0, 2) => ("LW", vec![xc(rdp), Mem]),
+        (0, 3) if rv64 => ("LD", vec![xc(rdp), Mem]),
+        (0, 3) => ("FLW", vec![fc(rdp), Mem]),
+        (0, 5) => ("FSD", vec![fc(rdp), Mem]),
+        (0, 6) => ("SW", vec![xc(rdp), Mem]),
+        (0, 7) if rv64 => ("SD", vec![xc(rdp), Mem]),
+        (0, 7) => ("FSW", vec![fc(rdp), Mem]),
+        (1, 0) => ("ADDI", vec![x(rd), x(rd), Imm]),
+        (1, 1) if rv64 => ("ADDIW", vec![x(rd), x(rd), Imm]),
+        (1, 1) => ("JAL", vec![x(1), Imm]),
+        (1, 2) => ("ADDI", vec![x(rd), x(0), Imm]),
+        (1, 3) if rd == 2 => ("ADDI", vec![x(2), x(2), Imm]),
+        (1, 3) => ("LUI", vec![x(rd), Imm]),
+        (1, 4) => match ((h >> 10) & 3, bit12, (h >> 5) & 3) {
+            (0, _, _) => ("SRLI", vec![xc(rs1p), xc(rs1p), Imm]),
+            (1, _, _) => ("SRAI", vec![xc(rs1p), xc(rs1p), Imm]),
+            (2, _, _) => ("ANDI", vec![xc(rs1p), xc(rs1p), Imm]),
+            (3, 0, op) => {
+                let m = ["SUB", "XOR", "OR", "AND"][op as usize];
+                (m, vec![xc(rs1p), xc(rs1p), xc(rdp)])
+            },
+            (3, 1, 0) if rv64 => ("SUBW", vec![xc(rs1p), xc(rs1p), xc(rdp)]),
+            (3, 1, 1) if rv64 => ("ADDW", vec![xc(rs1p), xc(rs1p), xc(rdp)]),
+            _ => return None,
+        },
+        (1, 5) => ("JAL", vec![x(0), Imm]),
+        (1, 6) => ("BEQ", vec![xc(rs1p), x(0), Imm]),
+        (1, 7) => ("BNE", vec![xc(rs1p), x(0), Imm]),
+        (2, 0) => ("SLLI", vec![x(rd), x(rd), Imm]),
+        (2, 1) => ("FLD", vec![f(rd), Mem]),
+        (2, 2) => ("LW", vec![x(rd), Mem]),
+        (2, 3) if rv64 => ("LD", vec![x(rd), Mem]),
+        (2, 3) => ("FLW", vec![f(rd), Mem]),
+        (2, 4) => match (bit12, rd, rs2) {
+            (0, 0, 0) => return None,
+            (0, _, 0) => ("JALR", vec![x(0), x(rd), Imm]),
+            (0, _, _) => ("ADD", vec![x(rd), x(0), x(rs2)]),
+            (1, 0, 0) => ("EBREAK", vec![]),
+            (1, _, 0) => ("JALR", vec![x(1), x(rd), Imm]),
+            _ => ("ADD", vec![x(rd), x(rd), x(rs2)]),
+        },
+        (2, 5) => ("FSD", vec![f(rs2), Mem]),
+        (2, 6) => ("SW", vec![x(rs2), Mem]),
+        (2, 7) if rv64 => ("SD", vec![x(rs2), Mem]),
+        (2, 7) => ("FSW", vec![f(rs2), Mem]),
+        _ => return None,
+    })
+}
+
+/// Rewrite the jump encodings into their conventional pseudo-instructions so
+/// returns and plain jumps are recognizable like on other ISAs.
+fn canonicalize(m: &'static str, ops: Vec<OperandKind>) -> Decoded {
+    let zero = x(0);
+    match m {
+        "JALR" if ops[0] == zero && ops[1] == x(1) => ("RET", vec![]),
+        "JALR" if ops[0] == zero => ("JR", ops[1..].to_vec()),
+        "JAL" if ops[0] == zero => ("J", ops[1..].to_vec()),
+        _ => (m, ops),
+    }
+}
+
+fn sext(v: u32, bits: u32) -> i64 {
+    ((v << (32 - bits)) as i32 >> (32 - bits)) as i64
+}
+
+/// PC-relative offset of a `JAL`/`B*` encoding or its compressed forms.
+fn branch_offset(w: u32, len: usize) -> Option<i64> {
+    if len == 2 {
+        return match (w & 3, w >> 13) {
+            // c.jal / c.j: offset[11|4|9:8|10|6|7|3:1|5]
+            (1, 1 | 5) => Some(sext(
+                ((w >> 12) & 1) << 11 | ((w >> 11) & 1) << 4 | ((w >> 9) & 3) << 8 | ((w >> 8) & 1) << 10
+                | ((w >> 7) & 1) << 6 | ((w >> 6) & 1) << 7 | ((w >> 3) & 7) << 1 | ((w >> 2) & 1) << 5,
+                12,
+            )),
+            // c.beqz / c.bnez: offset[8|4:3] and offset[7:6|2:1|5]
+            (1, 6 | 7) => Some(sext(
+                ((w >> 12) & 1) << 8 | ((w >> 10) & 3) << 3 | ((w >> 5) & 3) << 6 | ((w >> 3) & 3) << 1
+                | ((w >> 2) & 1) << 5,
+                9,
+            )),
+            _ => None,
+        };
+    }
+    match w & 0x7f {
+        0x6f => Some(sext(
+            (w >> 31) << 20 | ((w >> 21) & 0x3ff) << 1 | ((w >> 20) & 1) << 11 | ((w >> 12) & 0xff) << 12,
+            21,
+        )),
+        0x63 => Some(sext(
+            (w >> 31) << 12 | ((w >> 25) & 0x3f) << 5 | ((w >> 8) & 0xf) << 1 | ((w >> 7) & 1) << 11,
+            13,
+        )),
+        _ => None,
+    }
+}
+
+/// `auipc rX, hi` + `jalr rd, lo(rX)`: the `call` and `tail` sequences for
+/// targets beyond the reach of `jal`, as left by a link without relaxation.
+/// Decoded as the one `JAL` they stand for, with the target the pair
+/// computes, so whether the linker relaxed a call does not show as a change.
+fn far_call(bytes: &[u8], addr: u64) -> Option<(Decoded, u64)> {
+    let word = |at: usize| Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let (hi, lo) = (word(0)?, word(4)?);
+    let base = (hi >> 7) & 31;
+    if hi & 0x7f != 0x17 || base == 0 || lo & 0x707f != 0x67 || (lo >> 15) & 31 != base { return None; }
+    let link = (lo >> 7) & 31;
+    if link != base && link != 0 { return None; }
+    let off = (hi & 0xffff_f000) as i32 as i64 + sext(lo >> 20, 12);
+    Some((("JAL", vec![x(link), Imm]), addr.wrapping_add(off as u64)))
+}
+
+fn flow(mnemonic: &str, target: Option<u64>) -> Flow {
+    match mnemonic {
+        "RET" | "MRET" | "SRET" => Flow::Return,
+        "J" => Flow::Jump(target),
+        "JR" => Flow::Jump(None),
+        "JAL" => Flow::Call(target),
+        "JALR" => Flow::Call(None),
+        "BEQ" | "BNE" | "BLT" | "BGE" | "BLTU" | "BGEU" => Flow::Branch(target),
+        "EBREAK" => Flow::Trap,
+        _ => Flow::Next,
+    }
+}
+
+/// RISC-V backend for RV32GC / RV64GC. Compressed instructions are expanded
+/// to their 32-bit equivalents before normalization.
+pub struct RiscV {
+    rv64: bool,
+}
+
+impl RiscV {
+    pub fn new(rv64: bool) -> Self {
+        Self { rv64 }
+    }
+}
+
+impl RiscV {
+    /// Integer registers are 32 bits wide on RV32.
+    fn xlen(&self, mut ops: Vec<OperandKind>) -> Vec<OperandKind> {
+        if !self.rv64 {
+            for op in &mut ops {
+                if let OperandKind::Reg(_, class @ RegClass::Gpr(_)) = op {
+                    *class = RegClass::Gpr(32);
+                }
+            }
+        }
+        ops
+    }
+}
+
+impl Disassembler for RiscV {
+    fn name(&self) -> &'static str {
+        if self.rv64 { "riscv (rv64gc)" } else { "riscv (rv32gc)" }
+    }
+
+    fn decode(&self, bytes: &[u8], addr: u64) -> Option<(Insn, usize)> {
+        if let Some(((m, ops), target)) = far_call(bytes, addr) {
+            let (mnemonic, op_kinds) = canonicalize(m, ops);
+            let flow = flow(mnemonic, Some(target));
+            return Some((Insn { addr, mnemonic: mnemonic.to_string(), op_kinds: self.xlen(op_kinds), flow, callee: None }, 8));
+        }
+        let lo = u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]) as u32;
+        let (w, len) = if lo & 3 != 3 {
+            (lo, 2)
+        } else {
+            if lo & 0x1c == 0x1c { return None; } // 48-bit and longer encodings
+            let hi = u16::from_le_bytes([*bytes.get(2)?, *bytes.get(3)?]) as u32;
+            (lo | (hi << 16), 4)
+        };
+        let (m, ops) = if len == 2 { decode16(w, self.rv64)? } else { decode32(w, self.rv64)? };
+        let (mnemonic, op_kinds) = canonicalize(m, ops);
+        let target = branch_offset(w, len).map(|off| addr.wrapping_add(off as u64));
+        let flow = flow(mnemonic, target);
+        Some((Insn { addr, mnemonic: mnemonic.to_string(), op_kinds: self.xlen(op_kinds), flow, callee: None }, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(rv64: bool, bytes: &[u8], addr: u64) -> Option<(Insn, usize)> {
+        RiscV::new(rv64).decode(bytes, addr)
+    }
+
+    fn decode16(rv64: bool, h: u16) -> Insn {
+        let (insn, len) = decode(rv64, &h.to_le_bytes(), 0x1000).unwrap();
+        assert_eq!(len, 2);
+        insn
+    }
+
+    fn decode32(w: u32, addr: u64) -> Insn {
+        let (insn, len) = decode(true, &w.to_le_bytes(), addr).unwrap();
+        assert_eq!(len, 4);
+        insn
+    }
+
+    fn pair(hi: u32, lo: u32, addr: u64) -> (Insn, usize) {
+        let bytes: Vec<u8> = hi.to_le_bytes().into_iter().chain(lo.to_le_bytes()).collect();
+        decode(true, &bytes, addr).unwrap()
+    }
+
+    #[test]
+    fn compressed_addi_matches_addi() {
+        // c.addi a0, 1 / addi a0, a0, 1
+        let (c, full) = (decode16(true, 0x0505), decode32(0x0015_0513, 0x1000));
+        assert_eq!(c.mnemonic, "ADDI");
+        assert_eq!((c.mnemonic, c.op_kinds), (full.mnemonic, full.op_kinds));
+    }
+
+    #[test]
+    fn compressed_jr_ra_is_ret() {
+        let insn = decode16(true, 0x8082);
+        assert_eq!(insn.mnemonic, "RET");
+        assert_eq!(insn.flow, Flow::Return);
+    }
+
+    #[test]
+    fn compressed_branches_reach_backwards() {
+        // c.j .-2
+        let j = decode16(true, 0xbffd);
+        assert_eq!(j.mnemonic, "J");
+        assert_eq!(j.flow, Flow::Jump(Some(0x0ffe)));
+        // c.beqz a0, .-4
+        let beqz = decode16(true, 0xdd75);
+        assert_eq!(beqz.mnemonic, "BEQ");
+        assert_eq!(beqz.op_kinds, vec![x(10), x(0), Imm]);
+        assert_eq!(beqz.flow, Flow::Branch(Some(0x0ffc)));
+    }
+
+    #[test]
+    fn jal_ra_calls_its_target() {
+        // jal ra, .-8
+        let insn = decode32(0xff9f_f0ef, 0x2000);
+        assert_eq!(insn.mnemonic, "JAL");
+        assert_eq!(insn.flow, Flow::Call(Some(0x1ff8)));
+        // jal ra, .+0x100
+        assert_eq!(decode32(0x1000_00ef, 0x2000).flow, Flow::Call(Some(0x2100)));
+    }
+
+    #[test]
+    fn compressed_load_depends_on_xlen() {
+        // c.ld a0, 0(a1) on RV64 is c.flw fa0, 0(a1) on RV32.
+        let ld = decode16(true, 0x6188);
+        assert_eq!((ld.mnemonic.as_str(), ld.op_kinds), ("LD", vec![x(10), Mem]));
+        let flw = decode16(false, 0x6188);
+        assert_eq!((flw.mnemonic.as_str(), flw.op_kinds), ("FLW", vec![f(10), Mem]));
+    }
+
+    #[test]
+    fn rejects_48_bit_encodings() {
+        assert!(decode(true, &[0x1f, 0, 0, 0, 0, 0], 0x1000).is_none());
+    }
+
+    #[test]
+    fn auipc_jalr_call_gets_a_target() {
+        // auipc ra, 0x1; jalr ra, -4(ra)
+        let (insn, len) = pair(0x0000_1097, 0xffc0_80e7, 0x1000);
+        assert_eq!(len, 8);
+        assert_eq!(insn.mnemonic, "JAL");
+        assert_eq!(insn.flow, Flow::Call(Some(0x1ffc)));
+        // Decoded like the relaxed `jal ra`.
+        assert_eq!(insn.op_kinds, decode32(0x1000_00ef, 0x1000).op_kinds);
+    }
+
+    #[test]
+    fn auipc_jalr_tail_call_is_a_jump() {
+        // auipc t1, 0; jalr zero, 8(t1)
+        let (insn, len) = pair(0x0000_0317, 0x0083_0067, 0x1000);
+        assert_eq!(len, 8);
+        assert_eq!(insn.mnemonic, "J");
+        assert_eq!(insn.flow, Flow::Jump(Some(0x1008)));
+    }
+
+    #[test]
+    fn jalr_through_another_register_stays_indirect() {
+        // auipc ra, 0x1; jalr ra, 0(a0)
+        let (insn, len) = pair(0x0000_1097, 0x0005_00e7, 0x1000);
+        assert_eq!((insn.mnemonic.as_str(), len), ("AUIPC", 4));
+        assert_eq!(decode32(0x0005_00e7, 0x1004).flow, Flow::Call(None));
+    }
+
+    #[test]
+    fn integer_registers_follow_xlen() {
+        // c.addi a0, 1
+        assert_eq!(decode16(true, 0x0505).op_kinds[0], OperandKind::Reg("A0".into(), RegClass::Gpr(64)));
+        assert_eq!(decode16(false, 0x0505).op_kinds[0], OperandKind::Reg("A0".into(), RegClass::Gpr(32)));
+    }
+}
@@ -0,0 +1,223 @@
+use super::{Flow, Insn, OperandKind, RegClass, Disassembler};
+use crate::loader::BinaryImage;
+use yaxpeax_arch::LengthedInstruction;
+use yaxpeax_x86::protected_mode::{InstDecoder, Instruction, Opcode, Operand, RegSpec, register_class};
+
+fn reg_class(r: RegSpec) -> RegClass {
+    let class = r.class();
+    if [register_class::D, register_class::W, register_class::B].contains(&class) {
+        RegClass::Gpr(r.width() * 8)
+    } else if [register_class::X, register_class::Y, register_class::Z, register_class::ST, register_class::MM].contains(&class) {
+        RegClass::Float
+    } else {
+        RegClass::Other
+    }
+}
+
+fn normalize_operand(op: &Operand) -> OperandKind {
+    use OperandKind::*;
+    match op {
+        Operand::Register(r) => Reg(r.name().to_uppercase(), reg_class(*r)),
+        Operand::ImmediateI8(_)|Operand::ImmediateI16(_)|Operand::ImmediateI32(_)
+        |Operand::ImmediateU8(_)|Operand::ImmediateU16(_)|Operand::ImmediateU32(_)
+        => Imm,
+        op if op.is_memory() => Mem,
+        _ => Other,
+    }
+}
+
+fn flow(addr: u64, len: u64, insn: &Instruction) -> Flow {
+    // Relative branch operands are displacements from the next instruction.
+    let target = || match insn.operand(0) {
+        Operand::ImmediateI8(rel) => Some(addr.wrapping_add(len).wrapping_add(rel as i64 as u64)),
+        Operand::ImmediateI32(rel) => Some(addr.wrapping_add(len).wrapping_add(rel as i64 as u64)),
+        _ => None,
+    };
+    match insn.opcode() {
+        Opcode::RETURN | Opcode::RETF | Opcode::IRET | Opcode::IRETD | Opcode::IRETQ
+        | Opcode::SYSRET | Opcode::SYSEXIT => Flow::Return,
+        Opcode::JMP => Flow::Jump(target()),
+        Opcode::JMPF | Opcode::JMPE => Flow::Jump(None),
+        // `call $+5; pop reg` reads EIP in position-independent code.
+        Opcode::CALL if target() == Some(addr.wrapping_add(len)) => Flow::Next,
+        Opcode::CALL => Flow::Call(target()),
+        Opcode::CALLF => Flow::Call(None),
+        Opcode::JO | Opcode::JNO | Opcode::JB | Opcode::JNB | Opcode::JZ | Opcode::JNZ
+        | Opcode::JA | Opcode::JNA | Opcode::JS | Opcode::JNS | Opcode::JP | Opcode::JNP
+        | Opcode::JL | Opcode::JGE | Opcode::JLE | Opcode::JG | Opcode::JECXZ
+        | Opcode::LOOP | Opcode::LOOPZ | Opcode::LOOPNZ => Flow::Branch(target()),
+        Opcode::UD0 | Opcode::UD1 | Opcode::UD2 | Opcode::HLT => Flow::Trap,
+        _ => Flow::Next,
+    }
+}
+
+fn normalize_instruction(addr: u64, len: u64, insn: &Instruction) -> Insn {
+    let mnemonic = match (insn.opcode(), insn.operand(0)) {
+        // The bytes a `__stdcall` callee pops are its argument size.
+        (Opcode::RETURN, Operand::ImmediateU16(n)) => format!("RETURN_{}", n),
+        (op, _) => format!("{:?}", op),
+    };
+    let mut op_kinds = Vec::new();
+    for i in 0..insn.operand_count() {
+        op_kinds.push(normalize_operand(&insn.operand(i)));
+    }
+    Insn { addr, mnemonic, op_kinds, flow: flow(addr, len, insn), callee: None }
+}
+
+/// IA-32 backend on top of yaxpeax-x86's protected-mode decoder.
+///
+/// `__stdcall` epilogues (`ret imm16`) end the function like a plain `ret`
+/// and decode as `RETURN_<bytes>`, so a changed argument count shows up as a
+/// modification.
+pub struct X86 {
+    dec: InstDecoder,
+}
+
+impl X86 {
+    pub fn new() -> Self {
+        Self { dec: InstDecoder::default() }
+    }
+
+    fn decode_va(&self, bin: &BinaryImage, addr: u64) -> Option<Instruction> {
+        self.dec.decode_slice(bin.read_va(addr, 15)?).ok()
+    }
+}
+
+/// Register a `__x86.get_pc_thunk.<reg>` (`mov reg, [esp]; ret`) at `addr`
+/// loads EIP into.
+fn pc_thunk(bin: &BinaryImage, addr: u64) -> Option<&'static str> {
+    const REGS: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+    match *bin.read_va(addr, 4)? {
+        [0x8b, modrm, 0x24, 0xc3] if modrm & 0xc7 == 0x04 => Some(REGS[(modrm >> 3) as usize & 7]),
+        _ => None,
+    }
+}
+
+impl Default for X86 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Disassembler for X86 {
+    fn name(&self) -> &'static str {
+        "yaxpeax-x86 (protected mode)"
+    }
+
+    fn decode(&self, bytes: &[u8], addr: u64) -> Option<(Insn, usize)> {
+        let insn = self.dec.decode_slice(bytes).ok()?;
+        let len = insn.len().to_const() as u64;
+        Some((normalize_instruction(addr, len, &insn), len as usize))
+    }
+
+    /// Also resolves `call [__imp_Foo]`, calls through `jmp [__imp_Foo]`
+    /// import thunks, and names calls to a PC thunk after its register so
+    /// they read the same wherever the linker put it.
+    fn import(&self, bin: &BinaryImage, insn: &Insn) -> Option<String> {
+        let slot = |addr: u64| match self.decode_va(bin, addr)?.operand(0) {
+            Operand::DisplacementU32(a) => Some(a as u64),
+            _ => None,
+        };
+        match insn.flow {
+            Flow::Call(None) | Flow::Jump(None) => bin.imports.get(&slot(insn.addr)?).cloned(),
+            Flow::Call(Some(t)) | Flow::Jump(Some(t)) => bin.imports.get(&t).cloned()
+                .or_else(|| pc_thunk(bin, t).map(|r| format!("__x86.get_pc_thunk.{}", r)))
+                .or_else(|| {
+                    let thunk = self.decode_va(bin, t)?;
+                    if thunk.opcode() != Opcode::JMP { return None; }
+                    bin.imports.get(&slot(t)?).cloned()
+                }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{Abi, Architecture, Endianness};
+    use crate::loader::{ImageData, Segment};
+
+    fn decode(bytes: &[u8], addr: u64) -> Insn {
+        let (insn, len) = X86::new().decode(bytes, addr).unwrap();
+        assert_eq!(len, bytes.len());
+        insn
+    }
+
+    /// One executable segment holding `code` at 0x1000, importing `puts`
+    /// through the slot at 0x2000.
+    fn image(code: &[u8]) -> BinaryImage {
+        let segment = Segment {
+            name: "text".to_string(), vaddr: 0x1000, vsize: code.len() as u64, offset: 0, file_size: code.len() as u64,
+            read: true, write: false, exec: true, member: None,
+        };
+        BinaryImage {
+            path: "test".to_string(), arch: Architecture::X86, bits: 32, endianness: Endianness::Little,
+            abi: Abi::Windows, image_base: 0x1000, segments: vec![segment], functions: Vec::new(), mapping_symbols: Vec::new(),
+            patch_sites: Vec::new(), imports: [(0x2000, "puts".to_string())].into(), metadata: Default::default(),
+            data: ImageData::from(code.to_vec()),
+        }
+    }
+
+    #[test]
+    fn ret_imm16_keeps_the_popped_bytes() {
+        let stdcall = decode(&[0xc2, 0x08, 0x00], 0x1000);
+        assert_eq!(stdcall.mnemonic, "RETURN_8");
+        assert_eq!(stdcall.flow, Flow::Return);
+        assert_ne!(decode(&[0xc2, 0x0c, 0x00], 0x1000).mnemonic, stdcall.mnemonic);
+        assert_eq!(decode(&[0xc3], 0x1000).mnemonic, "RETURN");
+    }
+
+    #[test]
+    fn call_rel32_targets() {
+        // call 0x1105
+        assert_eq!(decode(&[0xe8, 0x00, 0x01, 0x00, 0x00], 0x1000).flow, Flow::Call(Some(0x1105)));
+        // call 0xff5
+        assert_eq!(decode(&[0xe8, 0xf0, 0xff, 0xff, 0xff], 0x1000).flow, Flow::Call(Some(0x0ff5)));
+        // call $+5 only pushes EIP.
+        assert_eq!(decode(&[0xe8, 0x00, 0x00, 0x00, 0x00], 0x1000).flow, Flow::Next);
+    }
+
+    #[test]
+    fn indirect_jump_through_table() {
+        // jmp [eax*4 + 0x2000]
+        let insn = decode(&[0xff, 0x24, 0x85, 0x00, 0x20, 0x00, 0x00], 0x1000);
+        assert_eq!(insn.flow, Flow::Jump(None));
+        assert_eq!(insn.op_kinds, vec![OperandKind::Mem]);
+    }
+
+    #[test]
+    fn resolves_import_slots_and_thunks() {
+        let code = [
+            0xff, 0x15, 0x00, 0x20, 0x00, 0x00, // 1000: call [0x2000]
+            0xe8, 0x01, 0x00, 0x00, 0x00,       // 1006: call 100c
+            0xc3,                               // 100b: ret
+            0xff, 0x25, 0x00, 0x20, 0x00, 0x00, // 100c: jmp [0x2000]
+            0xe8, 0x00, 0x00, 0x00, 0x00,       // 1012: call 1017
+            0x8b, 0x1c, 0x24, 0xc3,             // 1017: mov ebx, [esp]; ret
+        ];
+        let (bin, x86) = (image(&code), X86::new());
+        let import = |addr: u64, len: usize| {
+            let at = (addr - 0x1000) as usize;
+            x86.import(&bin, &decode(&code[at..at + len], addr))
+        };
+        assert_eq!(import(0x1000, 6).as_deref(), Some("puts"));
+        assert_eq!(import(0x1006, 5).as_deref(), Some("puts"));
+        let thunk_call = decode(&[0xe8, 0x00, 0x00, 0x00, 0x00], 0x1012);
+        assert_eq!(thunk_call.flow, Flow::Next);
+        // A call elsewhere to the same thunk.
+        let far = Insn { flow: Flow::Call(Some(0x1017)), ..thunk_call };
+        assert_eq!(x86.import(&bin, &far).as_deref(), Some("__x86.get_pc_thunk.bx"));
+    }
+
+    #[test]
+    fn registers_carry_their_class() {
+        let classes = |bytes: &[u8]| -> Vec<RegClass> {
+            decode(bytes, 0x1000).op_kinds.into_iter().map(|k| match k { OperandKind::Reg(_, c) => c, k => panic!("{:?}", k) }).collect()
+        };
+        assert_eq!(classes(&[0x89, 0xc8]), [RegClass::Gpr(32); 2]);                    // mov eax, ecx
+        assert_eq!(classes(&[0x88, 0xc8]), [RegClass::Gpr(8); 2]);                     // mov al, cl
+        assert_eq!(classes(&[0x8c, 0xd8]), [RegClass::Gpr(16), RegClass::Other]);      // mov ax, ds
+        assert_eq!(classes(&[0x66, 0x0f, 0x6e, 0xc0]), [RegClass::Float, RegClass::Gpr(32)]); // movd xmm0, eax
+    }
+}
@@ -1,69 +1,284 @@
+use super::{Flow, Insn, JumpTable, OperandKind, RegClass, Disassembler};
 use crate::loader::BinaryImage;
-use super::{Insn, OperandKind, FunctionIR};
-use anyhow::{Result, bail};
-use yaxpeax_arch::{Decoder, LengthedInstruction};
-use yaxpeax_x86::long_mode::{Arch as X86_64, InstDecoder, Instruction, Operand};
-use std::cmp::min;
+use yaxpeax_arch::LengthedInstruction;
+use yaxpeax_x86::long_mode::{InstDecoder, Instruction, Opcode, Operand, RegSpec, register_class};
+
+fn reg_class(r: RegSpec) -> RegClass {
+    let class = r.class();
+    if [register_class::Q, register_class::D, register_class::W, register_class::B, register_class::RB].contains(&class) {
+        RegClass::Gpr(r.width() * 8)
+    } else if [register_class::X, register_class::Y, register_class::Z, register_class::ST, register_class::MM].contains(&class) {
+        RegClass::Float
+    } else {
+        RegClass::Other
+    }
+}
 
 fn normalize_operand(op: &Operand) -> OperandKind {
     use OperandKind::*;
     match op {
-        Operand::Register(r) => Reg(format!("{:?}", r)),
+        Operand::Register(r) => Reg(r.name().to_uppercase(), reg_class(*r)),
         Operand::ImmediateI8(_)|Operand::ImmediateI16(_)|Operand::ImmediateI32(_)|Operand::ImmediateI64(_)
         |Operand::ImmediateU8(_)|Operand::ImmediateU16(_)|Operand::ImmediateU32(_)|Operand::ImmediateU64(_)
         => Imm,
-        Operand::DisplacementU32(_)|Operand::DisplacementU64(_)|Operand::RegDeref(_)|Operand::RegDeref2(_, _)
-        |Operand::RegDisp(_)|Operand::RegDeref3(_, _, _)|Operand::RegDispScaled(_, _, _)
-        => Mem,
+        op if op.is_memory() => Mem,
         _ => Other,
     }
 }
 
-fn normalize_instruction(addr: u64, insn: &Instruction) -> Insn {
+fn flow(addr: u64, len: u64, insn: &Instruction) -> Flow {
+    // Relative branch operands are displacements from the next instruction.
+    let target = || match insn.operand(0) {
+        Operand::ImmediateI8(rel) => Some(addr.wrapping_add(len).wrapping_add(rel as i64 as u64)),
+        Operand::ImmediateI32(rel) => Some(addr.wrapping_add(len).wrapping_add(rel as i64 as u64)),
+        _ => None,
+    };
+    match insn.opcode() {
+        Opcode::RETURN | Opcode::RETF | Opcode::IRET | Opcode::IRETD | Opcode::IRETQ
+        | Opcode::SYSRET | Opcode::SYSEXIT => Flow::Return,
+        Opcode::JMP => Flow::Jump(target()),
+        Opcode::JMPF | Opcode::JMPE => Flow::Jump(None),
+        Opcode::CALL => Flow::Call(target()),
+        Opcode::CALLF => Flow::Call(None),
+        Opcode::JO | Opcode::JNO | Opcode::JB | Opcode::JNB | Opcode::JZ | Opcode::JNZ
+        | Opcode::JA | Opcode::JNA | Opcode::JS | Opcode::JNS | Opcode::JP | Opcode::JNP
+        | Opcode::JL | Opcode::JGE | Opcode::JLE | Opcode::JG | Opcode::JRCXZ
+        | Opcode::LOOP | Opcode::LOOPZ | Opcode::LOOPNZ => Flow::Branch(target()),
+        Opcode::UD0 | Opcode::UD1 | Opcode::UD2 | Opcode::HLT => Flow::Trap,
+        _ => Flow::Next,
+    }
+}
+
+fn normalize_instruction(addr: u64, len: u64, insn: &Instruction) -> Insn {
     let mnemonic = format!("{:?}", insn.opcode());
     let mut op_kinds = Vec::new();
     for i in 0..insn.operand_count() {
         op_kinds.push(normalize_operand(&insn.operand(i)));
     }
-    Insn { addr, mnemonic, op_kinds }
+    Insn { addr, mnemonic, op_kinds, flow: flow(addr, len, insn), callee: None }
+}
+
+/// How far back along the path to look for the table setup and its bound.
+const LOOKBEHIND: usize = 16;
+
+/// Largest jump table we are willing to read.
+const MAX_CASES: u64 = 4096;
+
+fn imm_value(op: Operand) -> Option<i64> {
+    Some(match op {
+        Operand::ImmediateI8(v) => v as i64,
+        Operand::ImmediateU8(v) => v as i64,
+        Operand::ImmediateI16(v) => v as i64,
+        Operand::ImmediateU16(v) => v as i64,
+        Operand::ImmediateI32(v) => v as i64,
+        Operand::ImmediateU32(v) => v as i64,
+        Operand::ImmediateI64(v) => v,
+        _ => return None,
+    })
+}
+
+/// Number of cases from the closest `cmp idx, imm` + `ja`/`jae default`.
+/// `recent` is newest first.
+fn case_count(recent: &[(u64, Instruction)]) -> Option<u64> {
+    let k = recent.iter().position(|(_, i)| matches!(i.opcode(), Opcode::JA | Opcode::JNB))?;
+    let (_, cmp) = recent.get(k + 1)?;
+    if cmp.opcode() != Opcode::CMP { return None; }
+    let bound = imm_value(cmp.operand(1))?;
+    let count = if recent[k].1.opcode() == Opcode::JA { bound + 1 } else { bound };
+    (count > 0 && count as u64 <= MAX_CASES).then_some(count as u64)
+}
+
+/// Table address of the position-independent dispatch
+/// `lea base, [rip + table]; movsxd dst, [base + idx*4]; add dst, base; jmp dst`.
+fn pic_table(recent: &[(u64, Instruction)], dst: RegSpec) -> Option<u64> {
+    let mut it = recent.iter();
+    let base = it.find_map(|(_, i)| match (i.opcode(), i.operand(0), i.operand(1)) {
+        (Opcode::ADD, Operand::Register(d), Operand::Register(b)) if d == dst => Some(b),
+        _ => None,
+    })?;
+    it.find(|(_, i)| match (i.opcode(), i.operand(0), i.operand(1)) {
+        (Opcode::MOVSXD, Operand::Register(d), Operand::RegIndexBaseScale(b, _, 4)) => d == dst && b == base,
+        _ => false,
+    })?;
+    it.find_map(|(addr, i)| match (i.opcode(), i.operand(0), i.operand(1)) {
+        (Opcode::LEA, Operand::Register(r), Operand::RegDisp(rip, disp)) if r == base && rip == RegSpec::rip() => {
+            Some(addr.wrapping_add(i.len().to_const()).wrapping_add(disp as i64 as u64))
+        }
+        _ => None,
+    })
+}
+
+/// x86_64 backend on top of yaxpeax-x86's long-mode decoder.
+pub struct X86_64 {
+    dec: InstDecoder,
+}
+
+impl X86_64 {
+    pub fn new() -> Self {
+        Self { dec: InstDecoder::default() }
+    }
+
+    fn decode_va(&self, bin: &BinaryImage, addr: u64) -> Option<Instruction> {
+        self.dec.decode_slice(bin.read_va(addr, 15)?).ok()
+    }
+}
+
+impl Default for X86_64 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub fn disassemble_functions(bin: &BinaryImage) -> Result<Vec<FunctionIR>> {
-    if bin.arch != "x86" || bin.bits != 64 {
-        bail!("Currently only x86_64 is implemented with yaxpeax");
-    }
-    let dec = InstDecoder::default();
-    let bytes = &bin.data;
-    let mut out = Vec::new();
-    for f in &bin.functions {
-        let start = f.start as usize;
-        if start >= bytes.len() { continue; }
-        // Determine a conservative slice length
-        let max_len = if f.size > 0 { f.size as usize } else { min(bytes.len() - start, 4096) };
-        let mut cursor = 0usize;
-        let mut insns = Vec::new();
-        while cursor < max_len {
-            let off = start + cursor;
-            match dec.decode(&bytes[off..]) {
-                Ok(insn) => {
-                    let len = insn.len() as usize;
-                    if len == 0 { break; }
-                    insns.push(normalize_instruction((off) as u64, &insn));
-                    cursor += len;
-                    // crude stop on RET to prevent falling into padding
-                    if format!("{:?}", insn.opcode()).starts_with("RET") { break; }
-                },
-                Err(_) => break,
+impl Disassembler for X86_64 {
+    fn name(&self) -> &'static str {
+        "yaxpeax-x86 (long mode)"
+    }
+
+    fn decode(&self, bytes: &[u8], addr: u64) -> Option<(Insn, usize)> {
+        let insn = self.dec.decode_slice(bytes).ok()?;
+        let len = insn.len().to_const();
+        Some((normalize_instruction(addr, len, &insn), len as usize))
+    }
+
+    /// Also resolves calls through a memory slot (`call [rip + slot]` for
+    /// `-fno-plt` and `__declspec(dllimport)`) and through `jmp [slot]`
+    /// thunks.
+    fn import(&self, bin: &BinaryImage, insn: &Insn) -> Option<String> {
+        let slot = |addr: u64| {
+            let i = self.decode_va(bin, addr)?;
+            match i.operand(0) {
+                Operand::RegDisp(rip, disp) if rip == RegSpec::rip() => Some(addr.wrapping_add(i.len().to_const()).wrapping_add(disp as i64 as u64)),
+                Operand::DisplacementU32(a) => Some(a as u64),
+                Operand::DisplacementU64(a) => Some(a),
+                _ => None,
+            }
+        };
+        match insn.flow {
+            Flow::Call(None) | Flow::Jump(None) => bin.imports.get(&slot(insn.addr)?).cloned(),
+            Flow::Call(Some(t)) | Flow::Jump(Some(t)) => bin.imports.get(&t).cloned().or_else(|| {
+                let thunk = self.decode_va(bin, t)?;
+                if thunk.opcode() != Opcode::JMP { return None; }
+                bin.imports.get(&slot(t)?).cloned()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Recognizes `jmp [idx*8 + table]` with absolute 8-byte entries and the
+    /// RIP-relative form with 4-byte entries relative to the table, bounded
+    /// by the `cmp`/`ja` guarding the dispatch.
+    fn jump_table(&self, bin: &BinaryImage, path: &[u64], addr: u64) -> Option<JumpTable> {
+        let jmp = self.decode_va(bin, addr)?;
+        let recent: Vec<_> = path.iter().rev().take(LOOKBEHIND)
+            .map_while(|&a| Some((a, self.decode_va(bin, a)?)))
+            .collect();
+        let count = case_count(&recent)?;
+        let (table, targets): (u64, Vec<u64>) = match jmp.operand(0) {
+            Operand::RegScaleDisp(_, 8, disp) => {
+                let table = disp as i64 as u64;
+                let bytes = bin.read_va(table, (count * 8) as usize)?;
+                let targets = bytes.chunks_exact(8).map(|e| u64::from_le_bytes(e.try_into().unwrap())).collect();
+                (table, targets)
+            }
+            Operand::Register(dst) => {
+                let table = pic_table(&recent, dst)?;
+                let bytes = bin.read_va(table, (count * 4) as usize)?;
+                let targets = bytes.chunks_exact(4)
+                    .map(|e| table.wrapping_add(i32::from_le_bytes(e.try_into().unwrap()) as i64 as u64))
+                    .collect();
+                (table, targets)
             }
+            _ => return None,
+        };
+        if targets.len() as u64 != count || !targets.iter().all(|&t| bin.is_executable(t)) {
+            return None;
         }
-        if !insns.is_empty() {
-            out.push(FunctionIR {
-                name: f.name.clone(),
-                start: f.start,
-                size: f.size,
-                insns,
-            });
+        Some(JumpTable { jump: addr, table, targets })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{Abi, Architecture, Endianness};
+    use crate::loader::{ImageData, Segment};
+
+    const CODE: u64 = 0x1000;
+    const TABLE: u64 = 0x1100;
+
+    /// One executable segment with `code` at `CODE` and `table` at `TABLE`.
+    fn image(code: &[u8], table: &[u8]) -> BinaryImage {
+        let mut bytes = code.to_vec();
+        bytes.resize((TABLE - CODE) as usize, 0xcc);
+        bytes.extend_from_slice(table);
+        let segment = Segment {
+            name: "text".to_string(), vaddr: CODE, vsize: bytes.len() as u64, offset: 0, file_size: bytes.len() as u64,
+            read: true, write: false, exec: true, member: None,
+        };
+        BinaryImage {
+            path: "test".to_string(), arch: Architecture::X86_64, bits: 64, endianness: Endianness::Little,
+            abi: Abi::SysV, image_base: CODE, segments: vec![segment], functions: Vec::new(), mapping_symbols: Vec::new(),
+            patch_sites: Vec::new(), imports: Default::default(), metadata: Default::default(), data: ImageData::from(bytes),
         }
     }
-    Ok(out)
+
+    fn absolute(targets: &[u64]) -> Vec<u8> {
+        targets.iter().flat_map(|t| t.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn absolute_table() {
+        let code = [
+            0x83, 0xff, 0x03,                         // 1000: cmp edi, 3
+            0x77, 0x3b,                               // 1003: ja 1040
+            0xff, 0x24, 0xfd, 0x00, 0x11, 0x00, 0x00, // 1005: jmp [rdi*8 + 0x1100]
+        ];
+        let bin = image(&code, &absolute(&[0x1010, 0x1020, 0x1030, 0x1040]));
+        let table = X86_64::new().jump_table(&bin, &[0x1000, 0x1003], 0x1005).unwrap();
+        assert_eq!(table, JumpTable { jump: 0x1005, table: TABLE, targets: vec![0x1010, 0x1020, 0x1030, 0x1040] });
+    }
+
+    #[test]
+    fn pic_relative_table() {
+        let code = [
+            0x83, 0xff, 0x03,                         // 1000: cmp edi, 3
+            0x77, 0x3b,                               // 1003: ja 1040
+            0x48, 0x8d, 0x15, 0xf4, 0x00, 0x00, 0x00, // 1005: lea rdx, [rip + 0xf4] (0x1100)
+            0x48, 0x63, 0x04, 0xba,                   // 100c: movsxd rax, [rdx + rdi*4]
+            0x48, 0x01, 0xd0,                         // 1010: add rax, rdx
+            0xff, 0xe0,                               // 1013: jmp rax
+        ];
+        let entries: Vec<u8> = [-0xe0i32, -0xd0, -0xc0, -0xb0].iter().flat_map(|e| e.to_le_bytes()).collect();
+        let bin = image(&code, &entries);
+        let path = [0x1000, 0x1003, 0x1005, 0x100c, 0x1010];
+        let table = X86_64::new().jump_table(&bin, &path, 0x1013).unwrap();
+        assert_eq!(table, JumpTable { jump: 0x1013, table: TABLE, targets: vec![0x1020, 0x1030, 0x1040, 0x1050] });
+    }
+
+    #[test]
+    fn unbounded_table_is_not_resolved() {
+        let code = [
+            0x89, 0xf8,                               // 1000: mov eax, edi
+            0xff, 0x24, 0xfd, 0x00, 0x11, 0x00, 0x00, // 1002: jmp [rdi*8 + 0x1100]
+        ];
+        let bin = image(&code, &absolute(&[0x1010; 4]));
+        assert_eq!(X86_64::new().jump_table(&bin, &[0x1000], 0x1002), None);
+    }
+
+    #[test]
+    fn table_size_is_capped() {
+        let code = |ja: u8| [
+            0x81, 0xff, 0x00, 0x10, 0x00, 0x00,       // 1000: cmp edi, 0x1000
+            ja, 0x38,                                 // 1006: ja / jae 1040
+            0xff, 0x24, 0xfd, 0x00, 0x11, 0x00, 0x00, // 1008: jmp [rdi*8 + 0x1100]
+        ];
+        let entries = absolute(&[0x1040; MAX_CASES as usize + 1]);
+        // `jae` bounds the index to exactly MAX_CASES entries ...
+        let bin = image(&code(0x73), &entries);
+        let table = X86_64::new().jump_table(&bin, &[0x1000, 0x1006], 0x1008).unwrap();
+        assert_eq!(table.targets.len() as u64, MAX_CASES);
+        // ... and `ja` to one more, which is refused.
+        let bin = image(&code(0x77), &entries);
+        assert_eq!(X86_64::new().jump_table(&bin, &[0x1000, 0x1006], 0x1008), None);
+    }
 }
@@ -1,27 +1,31 @@
 use crate::analysis::hash::{FunctionHash, hamming};
 use crate::arch::FunctionIR;
 use crate::diff::{FunctionDelta, MatchKind};
+use crate::loader::demangle;
 use similar::{TextDiff, ChangeTag};
 use std::collections::HashMap;
 
 fn insn_lines(f: &FunctionIR) -> Vec<String> {
     f.insns.iter().map(|i| {
         let ops = i.op_kinds.iter().map(|k| match k {
-            crate::arch::OperandKind::Reg(r) => format!("REG({})", r),
+            crate::arch::OperandKind::Reg(r, _) => format!("REG({})", r),
             crate::arch::OperandKind::Mem => "MEM".to_string(),
             crate::arch::OperandKind::Imm => "IMM".to_string(),
             crate::arch::OperandKind::Other => "O".to_string(),
         }).collect::<Vec<_>>().join(",");
-        format!("{} {}", i.mnemonic, ops)
+        match &i.callee {
+            Some(c) => format!("{} {} -> {}", i.mnemonic, ops, c),
+            None => format!("{} {}", i.mnemonic, ops),
+        }
     }).collect()
 }
 
 fn unified_diff(a: &FunctionIR, b: &FunctionIR) -> String {
     let a_lines = insn_lines(a);
     let b_lines = insn_lines(b);
-    let diff = TextDiff::from_slices(&a_lines.join("
-"), &b_lines.join("
-"));
+    let a_lines: Vec<&str> = a_lines.iter().map(String::as_str).collect();
+    let b_lines: Vec<&str> = b_lines.iter().map(String::as_str).collect();
+    let diff = TextDiff::from_slices(&a_lines, &b_lines);
     let mut out = String::new();
     for change in diff.iter_all_changes() {
         let sign = match change.tag() {
@@ -30,34 +34,100 @@ fn unified_diff(a: &FunctionIR, b: &FunctionIR) -> String {
             ChangeTag::Equal => " ",
         };
         out.push_str(sign);
-        out.push_str(change.to_string().as_str());
-        out.push('
-');
+        out.push_str(change.value());
+        out.push('\n');
     }
     out
 }
 
+/// How `match_functions_with` pairs functions up.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchOptions {
+    /// Match Rust symbols on their demangled path, ignoring the hash or
+    /// crate disambiguator that changes whenever a crate is rebuilt.
+    pub rust_paths: bool,
+}
+
+/// Pairs each still unpaired A function with the unpaired B function of the
+/// same key, where that key occurs exactly once among the unpaired ones on
+/// each side. Functions without a key are left alone.
+fn pair_unique<K: std::hash::Hash + Eq>(
+    partner: &mut [Option<usize>],
+    used_b: &mut [bool],
+    key_a: impl Fn(usize) -> Option<K>,
+    key_b: impl Fn(usize) -> Option<K>,
+) {
+    let mut seen: HashMap<K, (Vec<usize>, Vec<usize>)> = HashMap::new();
+    for i in (0..partner.len()).filter(|&i| partner[i].is_none()) {
+        if let Some(k) = key_a(i) { seen.entry(k).or_default().0.push(i); }
+    }
+    for j in (0..used_b.len()).filter(|&j| !used_b[j]) {
+        if let Some(k) = key_b(j) { seen.entry(k).or_default().1.push(j); }
+    }
+    for (a, b) in seen.into_values() {
+        if let ([i], [j]) = (&a[..], &b[..]) {
+            partner[*i] = Some(*j);
+            used_b[*j] = true;
+        }
+    }
+}
+
 pub fn match_functions(
     fa: &[FunctionIR],
     fb: &[FunctionIR],
     ha: &[FunctionHash],
     hb: &[FunctionHash],
 ) -> (Vec<FunctionDelta>, Vec<FunctionDelta>, Vec<FunctionDelta>, Vec<FunctionDelta>) {
-    let mut by_name_b: HashMap<&str, usize> = HashMap::new();
-    for (i, h) in hb.iter().enumerate() {
-        by_name_b.insert(&h.name, i);
+    match_functions_with(fa, fb, ha, hb, &MatchOptions::default())
+}
+
+pub fn match_functions_with(
+    fa: &[FunctionIR],
+    fb: &[FunctionIR],
+    ha: &[FunctionHash],
+    hb: &[FunctionHash],
+    opts: &MatchOptions,
+) -> (Vec<FunctionDelta>, Vec<FunctionDelta>, Vec<FunctionDelta>, Vec<FunctionDelta>) {
+    let path = |h: &FunctionHash| if opts.rust_paths { demangle::rust_path(&h.name) } else { None };
+    let (paths_a, paths_b): (Vec<_>, Vec<_>) = (ha.iter().map(path).collect(), hb.iter().map(path).collect());
+    // Monomorphizations of one generic share a path, which then cannot say
+    // which of them pair up; only a path unique on both sides stands in for
+    // the symbol name.
+    let mut uses: HashMap<(Option<&str>, &str), (usize, usize)> = HashMap::new();
+    for (f, p) in fa.iter().zip(&paths_a) {
+        if let Some(p) = p { uses.entry((f.member.as_deref(), p)).or_default().0 += 1; }
     }
+    for (f, p) in fb.iter().zip(&paths_b) {
+        if let Some(p) = p { uses.entry((f.member.as_deref(), p)).or_default().1 += 1; }
+    }
+    let key = |f: &FunctionIR, h: &FunctionHash, path: &Option<String>| -> String {
+        match path {
+            Some(p) if matches!(uses.get(&(f.member.as_deref(), p.as_str())), Some(&(a, b)) if a <= 1 && b <= 1) => p.clone(),
+            _ => h.name.clone(),
+        }
+    };
+    // Archive members are matched member by member, so the same static
+    // name in two objects does not cross over.
+    let keys_a: Vec<_> = fa.iter().zip(ha).zip(&paths_a).map(|((f, h), p)| (f.member.as_deref(), key(f, h, p))).collect();
+    let keys_b: Vec<_> = fb.iter().zip(hb).zip(&paths_b).map(|((f, h), p)| (f.member.as_deref(), key(f, h, p))).collect();
+    // A name shared by several functions (statics from different sources)
+    // pairs only where the declaring file, or else identical code, tells
+    // the copies apart; the rest are reported as removed and added.
+    let mut partner = vec![None; ha.len()];
     let mut used_b = vec![false; hb.len()];
+    let file = |f: &FunctionIR| f.debug.as_ref().and_then(|d| d.file.clone());
+    pair_unique(&mut partner, &mut used_b, |i| Some(keys_a[i].clone()), |j| Some(keys_b[j].clone()));
+    pair_unique(&mut partner, &mut used_b, |i| Some((keys_a[i].clone(), file(&fa[i])?)), |j| Some((keys_b[j].clone(), file(&fb[j])?)));
+    pair_unique(&mut partner, &mut used_b, |i| Some((keys_a[i].clone(), ha[i].blake3)), |j| Some((keys_b[j].clone(), hb[j].blake3)));
 
     let mut unchanged = Vec::new();
     let mut modified = Vec::new();
     let mut removed = Vec::new();
     let mut added = Vec::new();
 
-    // First pass: exact name matches
+    // First pass: functions paired by name
     for (i, ha_i) in ha.iter().enumerate() {
-        if let Some(&j) = by_name_b.get(ha_i.name.as_str()) {
-            used_b[j] = true;
+        if let Some(j) = partner[i] {
             let fb_i = &fb[j];
             let fa_i = &fa[i];
             if ha_i.blake3 == hb[j].blake3 {
@@ -71,6 +141,12 @@ pub fn match_functions(
                     insn_count_a: Some(ha_i.n_insn),
                     insn_count_b: Some(hb[j].n_insn),
                     unified_diff: None,
+                    debug_a: fa_i.debug.clone(),
+                    debug_b: fb_i.debug.clone(),
+                    member_a: fa_i.member.clone(),
+                    member_b: fb_i.member.clone(),
+                    demangled_a: fa_i.demangled.clone(),
+                    demangled_b: fb_i.demangled.clone(),
                 });
             } else {
                 let ham = hamming(ha_i.simhash, hb[j].simhash);
@@ -84,6 +160,12 @@ pub fn match_functions(
                     insn_count_a: Some(ha_i.n_insn),
                     insn_count_b: Some(hb[j].n_insn),
                     unified_diff: Some(unified_diff(&fa[i], fb_i)),
+                    debug_a: fa_i.debug.clone(),
+                    debug_b: fb_i.debug.clone(),
+                    member_a: fa_i.member.clone(),
+                    member_b: fb_i.member.clone(),
+                    demangled_a: fa_i.demangled.clone(),
+                    demangled_b: fb_i.demangled.clone(),
                 });
             }
         } else {
@@ -97,6 +179,12 @@ pub fn match_functions(
                 insn_count_a: Some(ha_i.n_insn),
                 insn_count_b: None,
                 unified_diff: None,
+                debug_a: fa[i].debug.clone(),
+                debug_b: None,
+                member_a: fa[i].member.clone(),
+                member_b: None,
+                demangled_a: fa[i].demangled.clone(),
+                demangled_b: None,
             });
         }
     }
@@ -114,9 +202,72 @@ pub fn match_functions(
                 insn_count_a: None,
                 insn_count_b: Some(hb_j.n_insn),
                 unified_diff: None,
+                debug_a: None,
+                debug_b: fb[j].debug.clone(),
+                member_a: None,
+                member_b: fb[j].member.clone(),
+                demangled_a: None,
+                demangled_b: fb[j].demangled.clone(),
             });
         }
     }
 
     (added, removed, modified, unchanged)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::hash::hash_function;
+    use crate::arch::{Flow, Insn};
+    use crate::loader::DebugInfo;
+
+    /// A function whose body is the instructions `mnemonics`.
+    fn fun(name: &str, file: Option<&str>, mnemonics: &[&str]) -> FunctionIR {
+        let insns = mnemonics.iter().map(|m| Insn { addr: 0, mnemonic: m.to_string(), op_kinds: Vec::new(), flow: Flow::Next, callee: None }).collect();
+        FunctionIR {
+            name: name.to_string(), start: 0, size: 0, insns, coverage: Vec::new(), jump_tables: Vec::new(),
+            debug: file.map(|f| DebugInfo { file: Some(f.to_string()), line: None, inlined: Vec::new() }), member: None, demangled: None,
+        }
+    }
+
+    /// `(name_a, name_b, changed)` of each pair, and the counts of removed
+    /// and added functions.
+    fn pairs(fa: &[FunctionIR], fb: &[FunctionIR]) -> (Vec<(String, String, bool)>, usize, usize) {
+        let (ha, hb): (Vec<_>, Vec<_>) = (fa.iter().map(hash_function).collect(), fb.iter().map(hash_function).collect());
+        let (added, removed, modified, unchanged) = match_functions(fa, fb, &ha, &hb);
+        let mut out: Vec<_> = unchanged.iter().chain(&modified)
+            .map(|d| (d.name_a.clone().unwrap(), d.name_b.clone().unwrap(), d.changed))
+            .collect();
+        out.sort();
+        (out, removed.len(), added.len())
+    }
+
+    #[test]
+    fn unique_names_pair_up() {
+        let fa = [fun("main", None, &["RET"]), fun("helper", None, &["NOP", "RET"])];
+        let fb = [fun("helper", None, &["RET"]), fun("main", None, &["RET"])];
+        let expected = vec![("helper".into(), "helper".into(), true), ("main".into(), "main".into(), false)];
+        assert_eq!(pairs(&fa, &fb), (expected, 0, 0));
+    }
+
+    #[test]
+    fn duplicate_names_pair_by_declaring_file() {
+        let fa = [fun("init", Some("/src/a.c"), &["NOP", "RET"]), fun("init", Some("/src/b.c"), &["RET"])];
+        let fb = [fun("init", Some("/src/b.c"), &["NOP", "NOP", "RET"]), fun("init", Some("/src/a.c"), &["NOP", "RET"])];
+        let (pairs, removed, added) = pairs(&fa, &fb);
+        // a.c is unchanged and b.c modified, rather than both crossed over.
+        assert_eq!(pairs, [("init".into(), "init".into(), false), ("init".into(), "init".into(), true)]);
+        assert_eq!((removed, added), (0, 0));
+    }
+
+    #[test]
+    fn duplicate_names_without_files_pair_only_identical_code() {
+        let fa = [fun("init", None, &["NOP", "RET"]), fun("init", None, &["RET"])];
+        let fb = [fun("init", None, &["NOP", "NOP", "RET"]), fun("init", None, &["RET"])];
+        assert_eq!(pairs(&fa, &fb), (vec![("init".into(), "init".into(), false)], 1, 1));
+        // One name against two: no arbitrary pick either.
+        let fb = [fun("init", None, &["NOP", "NOP", "RET"]), fun("init", None, &["HLT"])];
+        assert_eq!(pairs(&fa[..1], &fb), (vec![], 1, 2));
+    }
+}
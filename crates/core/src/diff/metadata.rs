@@ -0,0 +1,130 @@
+//! Differences between two images outside their functions: headers,
+//! sections, segment permissions, linked libraries, imports and mitigations.
+
+use crate::loader::metadata::rwx;
+use crate::loader::{BinaryImage, Segment};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A named value that differs; `None` where one side does not have it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FieldChange {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// A section added (`old_size` is `None`), removed, resized or with new
+/// flags.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SectionChange {
+    pub name: String,
+    pub old_size: Option<u64>,
+    pub new_size: Option<u64>,
+    pub old_flags: Option<String>,
+    pub new_flags: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SetChange {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl SetChange {
+    fn new<'a>(a: impl IntoIterator<Item = &'a String>, b: impl IntoIterator<Item = &'a String>) -> Self {
+        let (a, b): (BTreeSet<_>, BTreeSet<_>) = (a.into_iter().collect(), b.into_iter().collect());
+        Self {
+            added: b.difference(&a).map(|s| s.to_string()).collect(),
+            removed: a.difference(&b).map(|s| s.to_string()).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetadataDiff {
+    /// Architecture, pointer width, byte order, ABI, entry point and build ID.
+    pub headers: Vec<FieldChange>,
+    pub sections: Vec<SectionChange>,
+    /// Segments added, removed or with new permissions, as `rwx` strings.
+    pub segments: Vec<FieldChange>,
+    /// `DT_NEEDED` entries, PE import DLLs or Mach-O dylibs.
+    pub libraries: SetChange,
+    /// Imported functions.
+    pub imports: SetChange,
+    pub hardening: Vec<FieldChange>,
+}
+
+impl MetadataDiff {
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.sections.is_empty() && self.segments.is_empty()
+            && self.libraries.is_empty() && self.imports.is_empty() && self.hardening.is_empty()
+    }
+}
+
+/// Changes between two maps, in key order.
+fn changed_fields(a: &BTreeMap<String, String>, b: &BTreeMap<String, String>) -> Vec<FieldChange> {
+    let keys: BTreeSet<_> = a.keys().chain(b.keys()).collect();
+    keys.into_iter()
+        .filter(|k| a.get(*k) != b.get(*k))
+        .map(|k| FieldChange { name: k.clone(), old: a.get(k).cloned(), new: b.get(k).cloned() })
+        .collect()
+}
+
+fn headers(bin: &BinaryImage) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    out.insert("arch".to_string(), bin.arch.to_string());
+    out.insert("bits".to_string(), bin.bits.to_string());
+    out.insert("endianness".to_string(), bin.endianness.to_string());
+    out.insert("abi".to_string(), bin.abi.to_string());
+    if let Some(entry) = bin.metadata.entry { out.insert("entry".to_string(), format!("0x{:x}", entry)); }
+    if let Some(id) = &bin.metadata.build_id { out.insert("build_id".to_string(), id.clone()); }
+    out
+}
+
+fn permissions(segments: &[Segment]) -> BTreeMap<String, String> {
+    segments.iter().map(|s| {
+        let name = match &s.member { Some(m) => format!("{}:{}", m, s.name), None => s.name.clone() };
+        (name, rwx(s.read, s.write, s.exec))
+    }).collect()
+}
+
+/// Section sizes and flags by name; repeated names get `#2`, `#3`, ... in
+/// file order.
+fn sections(bin: &BinaryImage) -> BTreeMap<String, (u64, &str)> {
+    let mut seen: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut out = BTreeMap::new();
+    for s in &bin.metadata.sections {
+        let n = seen.entry(&s.name).or_default();
+        *n += 1;
+        let name = if *n == 1 { s.name.clone() } else { format!("{}#{}", s.name, n) };
+        out.insert(name, (s.size, s.flags.as_str()));
+    }
+    out
+}
+
+pub fn diff_metadata(a: &BinaryImage, b: &BinaryImage) -> MetadataDiff {
+    let (sa, sb) = (sections(a), sections(b));
+    let names: BTreeSet<_> = sa.keys().chain(sb.keys()).collect();
+    let sections = names.into_iter()
+        .filter(|n| sa.get(*n) != sb.get(*n))
+        .map(|n| {
+            let (old, new) = (sa.get(n), sb.get(n));
+            let flags = |s: Option<&(u64, &str)>| s.map(|&(_, f)| f.to_string());
+            SectionChange { name: n.clone(), old_size: old.map(|s| s.0), new_size: new.map(|s| s.0), old_flags: flags(old), new_flags: flags(new) }
+        })
+        .collect();
+
+    MetadataDiff {
+        headers: changed_fields(&headers(a), &headers(b)),
+        sections,
+        segments: changed_fields(&permissions(&a.segments), &permissions(&b.segments)),
+        libraries: SetChange::new(&a.metadata.libraries, &b.metadata.libraries),
+        imports: SetChange::new(a.imports.values(), b.imports.values()),
+        hardening: changed_fields(&a.metadata.hardening, &b.metadata.hardening),
+    }
+}
@@ -1,7 +1,12 @@
 pub mod report;
 pub mod matching;
+pub mod metadata;
 
+pub use metadata::MetadataDiff;
+
+use crate::loader::DebugInfo;
 use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MatchKind {
@@ -21,6 +26,46 @@ pub struct FunctionDelta {
     pub insn_count_a: Option<usize>,
     pub insn_count_b: Option<usize>,
     pub unified_diff: Option<String>,
+    /// Source location and inlining of each side, from debug info.
+    #[serde(default)]
+    pub debug_a: Option<DebugInfo>,
+    #[serde(default)]
+    pub debug_b: Option<DebugInfo>,
+    /// Archive member of each side, for archive inputs.
+    #[serde(default)]
+    pub member_a: Option<String>,
+    #[serde(default)]
+    pub member_b: Option<String>,
+    /// Demangled `name_a`/`name_b`, for mangled names.
+    #[serde(default)]
+    pub demangled_a: Option<String>,
+    #[serde(default)]
+    pub demangled_b: Option<String>,
+}
+
+impl FunctionDelta {
+    /// The archive member to file this delta under: B's, or A's for removals.
+    pub fn member(&self) -> Option<&str> {
+        self.member_b.as_deref().or(self.member_a.as_deref())
+    }
+
+    /// Name of side A for display: demangled when possible.
+    pub fn display_a(&self) -> Option<&str> {
+        self.demangled_a.as_deref().or(self.name_a.as_deref())
+    }
+
+    pub fn display_b(&self) -> Option<&str> {
+        self.demangled_b.as_deref().or(self.name_b.as_deref())
+    }
+}
+
+/// Delta counts for one archive member.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemberSummary {
+    pub unchanged: usize,
+    pub modified: usize,
+    pub added: usize,
+    pub removed: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,3 +75,24 @@ pub struct DiffResult {
     pub modified: Vec<FunctionDelta>,
     pub unchanged: Vec<FunctionDelta>,
 }
+
+impl DiffResult {
+    /// Counts per archive member; empty unless the inputs were archives.
+    pub fn member_summary(&self) -> BTreeMap<String, MemberSummary> {
+        let mut out: BTreeMap<String, MemberSummary> = BTreeMap::new();
+        let groups = [&self.unchanged, &self.modified, &self.added, &self.removed];
+        for (group, deltas) in groups.into_iter().enumerate() {
+            for d in deltas {
+                let Some(member) = d.member() else { continue };
+                let m = out.entry(member.to_string()).or_default();
+                match group {
+                    0 => m.unchanged += 1,
+                    1 => m.modified += 1,
+                    2 => m.added += 1,
+                    _ => m.removed += 1,
+                }
+            }
+        }
+        out
+    }
+}
@@ -1,5 +1,6 @@
 use serde::{Serialize, Deserialize};
-use crate::diff::{DiffResult, FunctionDelta, MatchKind};
+use crate::diff::{DiffResult, FunctionDelta, MemberSummary, MetadataDiff};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonReport {
@@ -7,15 +8,31 @@ pub struct JsonReport {
     pub removed: Vec<FunctionDelta>,
     pub modified: Vec<FunctionDelta>,
     pub unchanged: Vec<FunctionDelta>,
+    /// Counts per archive member, when the inputs were archives.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub members: BTreeMap<String, MemberSummary>,
+    /// Header, section, library and hardening changes, when computed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MetadataDiff>,
+}
+
+impl JsonReport {
+    pub fn with_metadata(mut self, metadata: MetadataDiff) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
 }
 
 impl From<DiffResult> for JsonReport {
     fn from(d: DiffResult) -> Self {
+        let members = d.member_summary();
         Self {
             added: d.added,
             removed: d.removed,
             modified: d.modified,
             unchanged: d.unchanged,
+            members,
+            metadata: None,
         }
     }
 }
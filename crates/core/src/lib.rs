@@ -5,5 +5,7 @@ pub mod arch;
 pub mod analysis;
 pub mod diff;
 
+pub use arch::{Abi, Architecture, Disassembler, Endianness, TargetArch};
+pub use analysis::cfg::{BasicBlock, ControlFlowGraph, Edge, EdgeKind};
 pub use analysis::hash::{FunctionHash, SimHash64};
-pub use diff::{DiffResult, FunctionDelta, MatchKind};
+pub use diff::{DiffResult, FunctionDelta, MatchKind, MetadataDiff};
@@ -0,0 +1,79 @@
+//! Locating the separate debug file of a stripped ELF image, the way GDB
+//! does: by build ID under `.build-id/`, then by `.gnu_debuglink`.
+
+use super::{ImageData, LoadOptions, elf_section, elf_section_data};
+use goblin::elf;
+use std::path::{Path, PathBuf};
+
+/// Global debug directory distributions install `-dbg`/`-debuginfo` packages into.
+const DEBUG_ROOT: &str = "/usr/lib/debug";
+
+/// Contents of the `NT_GNU_BUILD_ID` note.
+pub fn build_id(elf: &elf::Elf, data: &[u8]) -> Option<Vec<u8>> {
+    // Debug files keep the note section but may lack program headers.
+    let sections = elf.iter_note_sections(data, None).into_iter().flatten();
+    let segments = elf.iter_note_headers(data).into_iter().flatten();
+    sections.chain(segments)
+        .flatten()
+        .find(|n| n.n_type == elf::note::NT_GNU_BUILD_ID && n.name.trim_end_matches('\0') == "GNU")
+        .map(|n| n.desc.to_vec())
+}
+
+/// File name and CRC32 from `.gnu_debuglink`.
+fn debuglink(elf: &elf::Elf, data: &[u8]) -> Option<(String, u32)> {
+    let bytes = elf_section_data(data, elf_section(elf, ".gnu_debuglink")?)?;
+    let len = bytes.iter().position(|&b| b == 0)?;
+    let name = std::str::from_utf8(&bytes[..len]).ok()?.to_string();
+    // The CRC follows the name, padded to a four-byte boundary.
+    let at = (len + 4) & !3;
+    let crc = bytes.get(at..at + 4)?;
+    let crc = if elf.little_endian { u32::from_le_bytes(crc.try_into().ok()?) } else { u32::from_be_bytes(crc.try_into().ok()?) };
+    Some((name, crc))
+}
+
+/// The IEEE CRC-32 `.gnu_debuglink` records.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn has_build_id(path: &Path, id: &[u8]) -> bool {
+    let Ok(data) = ImageData::map(path) else { return false };
+    let Ok(elf) = elf::Elf::parse(&data) else { return false };
+    build_id(&elf, &data).as_deref() == Some(id)
+}
+
+fn has_crc(path: &Path, crc: u32) -> bool {
+    ImageData::map(path).is_ok_and(|data| crc32(&data) == crc)
+}
+
+/// The debug file for `image`, searching `--symbol-path` directories before
+/// the global debug directory. Candidates must carry the same build ID or,
+/// for debuglink lookups, the recorded CRC.
+pub fn find(image: &Path, elf: &elf::Elf, data: &[u8], opts: &LoadOptions) -> Option<PathBuf> {
+    let roots: Vec<PathBuf> = opts.symbol_paths.iter().cloned().chain([PathBuf::from(DEBUG_ROOT)]).collect();
+
+    if let Some(id) = build_id(elf, data).filter(|id| id.len() >= 2) {
+        let hex: String = id.iter().map(|b| format!("{:02x}", b)).collect();
+        let rel = Path::new(".build-id").join(&hex[..2]).join(format!("{}.debug", &hex[2..]));
+        if let Some(p) = roots.iter().map(|r| r.join(&rel)).find(|p| p.is_file() && has_build_id(p, &id)) {
+            return Some(p);
+        }
+    }
+
+    let (name, crc) = debuglink(elf, data)?;
+    let dir = image.canonicalize().ok()?.parent()?.to_path_buf();
+    let mut candidates = vec![dir.join(&name), dir.join(".debug").join(&name)];
+    for root in &roots {
+        // `/usr/lib/debug/usr/bin/foo.debug` mirrors the image's directory.
+        candidates.push(root.join(dir.strip_prefix("/").unwrap_or(&dir)).join(&name));
+        candidates.push(root.join(&name));
+    }
+    candidates.into_iter().find(|p| p.is_file() && has_crc(p, crc))
+}
@@ -0,0 +1,31 @@
+//! Readable names for Itanium C++, MSVC, Rust (legacy and v0) and Swift
+//! symbols, including Mach-O's extra leading underscore.
+
+use super::FunctionSymbol;
+use symbolic_common::{Language, Name, NameMangling};
+use symbolic_demangle::{Demangle, DemangleOptions};
+
+/// The demangled form of `raw`, with C++ parameter lists and without Rust
+/// hashes; `None` for names that are not mangled.
+pub fn demangle(raw: &str) -> Option<String> {
+    Name::new(raw, NameMangling::Unknown, Language::Unknown)
+        .demangle(DemangleOptions::complete())
+        .filter(|d| d != raw)
+}
+
+/// The path of a Rust symbol without its hash (legacy) or crate
+/// disambiguator (v0), which change between otherwise identical builds.
+pub fn rust_path(raw: &str) -> Option<String> {
+    let name = Name::new(raw, NameMangling::Unknown, Language::Unknown);
+    if name.detect_language() != Language::Rust { return None; }
+    demangle(raw)
+}
+
+/// Fill in `demangled` for every symbol that has a mangled name.
+pub fn annotate(funs: &mut [FunctionSymbol]) {
+    for f in funs {
+        if f.demangled.is_none() {
+            f.demangled = demangle(&f.name);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Function names, bounds, declaring file/line and inlined callees from
+//! DWARF `.debug_info`.
+
+use super::{DebugInfo, FunctionSymbol, elf_section, elf_section_data, synthetic_name};
+use gimli::{AttributeValue, DebugInfoOffset, DebuggingInformationEntry, DwAt, EndianSlice, RunTimeEndian, Unit, UnitOffset};
+use goblin::elf;
+use std::borrow::Cow;
+
+type Reader<'a> = EndianSlice<'a, RunTimeEndian>;
+type Dwarf<'a> = gimli::Dwarf<Reader<'a>>;
+
+/// `DW_AT_specification` / `DW_AT_abstract_origin` chains deeper than this
+/// are treated as malformed.
+const MAX_ORIGIN_DEPTH: usize = 8;
+
+/// Contents of debug section `name`, inflating `SHF_COMPRESSED` sections and
+/// the older GNU `.zdebug_*` form.
+fn section_data<'a>(elf: &elf::Elf, data: &'a [u8], name: &str) -> Option<Cow<'a, [u8]>> {
+    if let Some(sh) = elf_section(elf, name) {
+        let bytes = elf_section_data(data, sh)?;
+        if sh.sh_flags & elf::section_header::SHF_COMPRESSED as u64 == 0 {
+            return Some(Cow::Borrowed(bytes));
+        }
+        // Elf32_Chdr / Elf64_Chdr; only zlib is in use.
+        let header_len = if elf.is_64 { 24 } else { 12 };
+        let ch_type: [u8; 4] = bytes.get(..4)?.try_into().ok()?;
+        let ch_type = if elf.little_endian { u32::from_le_bytes(ch_type) } else { u32::from_be_bytes(ch_type) };
+        if ch_type != elf::compression_header::ELFCOMPRESS_ZLIB { return None; }
+        return inflate(bytes.get(header_len..)?);
+    }
+    // "ZLIB" followed by the big-endian uncompressed size.
+    let bytes = elf_section_data(data, elf_section(elf, &name.replacen(".debug_", ".zdebug_", 1))?)?;
+    if !bytes.starts_with(b"ZLIB") { return None; }
+    inflate(bytes.get(12..)?)
+}
+
+fn inflate(bytes: &[u8]) -> Option<Cow<'static, [u8]>> {
+    miniz_oxide::inflate::decompress_to_vec_zlib(bytes).ok().map(Cow::Owned)
+}
+
+/// One `FunctionSymbol` per `DW_TAG_subprogram` with code. The range holding
+/// the entry point becomes the body and any others its fragments.
+pub fn functions(elf: &elf::Elf, data: &[u8], addr_mask: u64) -> Vec<FunctionSymbol> {
+    let endian = if elf.little_endian { RunTimeEndian::Little } else { RunTimeEndian::Big };
+    let Ok(sections) = gimli::DwarfSections::load(|id| -> Result<Cow<[u8]>, gimli::Error> {
+        Ok(section_data(elf, data, id.name()).unwrap_or(Cow::Borrowed(&[])))
+    }) else { return Vec::new() };
+    let dwarf = sections.borrow(|s| EndianSlice::new(s, endian));
+    dwarf_functions(&dwarf, addr_mask)
+}
+
+fn dwarf_functions(dwarf: &Dwarf, addr_mask: u64) -> Vec<FunctionSymbol> {
+    // All units up front: abstract origins may point into any of them (LTO).
+    let mut units = Vec::new();
+    let mut headers = dwarf.units();
+    while let Ok(Some(header)) = headers.next() {
+        if let Ok(unit) = dwarf.unit(header) { units.push(unit); }
+    }
+    let mut out = Vec::new();
+    for unit in &units {
+        // A malformed unit costs only its own functions.
+        let _ = unit_functions(dwarf, &units, unit, addr_mask, &mut out);
+    }
+    out
+}
+
+fn unit_functions<'a>(dwarf: &Dwarf<'a>, units: &[Unit<Reader<'a>>], unit: &Unit<Reader<'a>>, addr_mask: u64, out: &mut Vec<FunctionSymbol>) -> gimli::Result<()> {
+    let mut entries = unit.entries();
+    let mut depth = 0isize;
+    // Enclosing subprograms that produced a function: (depth, index in `out`).
+    let mut enclosing: Vec<(isize, usize)> = Vec::new();
+    while let Some((delta, entry)) = entries.next_dfs()? {
+        depth += delta;
+        while enclosing.last().is_some_and(|&(d, _)| d >= depth) {
+            enclosing.pop();
+        }
+        match entry.tag() {
+            gimli::DW_TAG_subprogram => {
+                if let Some(f) = subprogram(dwarf, units, unit, entry, addr_mask)? {
+                    enclosing.push((depth, out.len()));
+                    out.push(f);
+                }
+            }
+            gimli::DW_TAG_inlined_subroutine => {
+                let Some(&(_, i)) = enclosing.last() else { continue };
+                let Some(name) = name(dwarf, units, unit, entry, &[gimli::DW_AT_name]) else { continue };
+                let inlined = &mut out[i].debug.get_or_insert_with(DebugInfo::default).inlined;
+                if !inlined.contains(&name) { inlined.push(name); }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn subprogram<'a>(dwarf: &Dwarf<'a>, units: &[Unit<Reader<'a>>], unit: &Unit<Reader<'a>>, entry: &DebuggingInformationEntry<Reader<'a>>, addr_mask: u64) -> gimli::Result<Option<FunctionSymbol>> {
+    let mut ranges = Vec::new();
+    let mut iter = dwarf.die_ranges(unit, entry)?;
+    while let Some(r) = iter.next()? {
+        // Ranges of discarded code are relocated to zero.
+        let (begin, end) = (r.begin & addr_mask, r.end & addr_mask);
+        if begin != 0 && end > begin { ranges.push((begin, end)); }
+    }
+    let mut entry_pc = None;
+    for attr in [gimli::DW_AT_entry_pc, gimli::DW_AT_low_pc] {
+        if let Some(v) = entry.attr_value(attr)? {
+            entry_pc = entry_pc.or(dwarf.attr_address(unit, v)?.map(|a| a & addr_mask));
+        }
+    }
+    // Split functions carry only `DW_AT_ranges`; compilers list the entry part first.
+    let Some(start) = entry_pc.or_else(|| ranges.first().map(|r| r.0)) else { return Ok(None) };
+    // Functions the linker discarded keep their DIE with a zero address.
+    if start == 0 || ranges.is_empty() { return Ok(None); }
+    let main = ranges.iter().position(|r| r.0 <= start && start < r.1).unwrap_or(0);
+    let (_, end) = ranges.remove(main);
+
+    // The index is into the line table of the unit declaring the function.
+    let file = match inherited(units, unit, entry, gimli::DW_AT_decl_file) {
+        Some((decl_unit, AttributeValue::FileIndex(index))) => file_name(dwarf, decl_unit, index),
+        _ => None,
+    };
+    let line = inherited(units, unit, entry, gimli::DW_AT_decl_line).and_then(|(_, v)| v.udata_value()).map(|l| l as u32);
+    Ok(Some(FunctionSymbol {
+        name: name(dwarf, units, unit, entry, &[gimli::DW_AT_linkage_name, gimli::DW_AT_MIPS_linkage_name, gimli::DW_AT_name])
+            .unwrap_or_else(|| synthetic_name(start)),
+        start,
+        size: end.saturating_sub(start),
+        fragments: ranges,
+        debug: Some(DebugInfo { file, line, inlined: Vec::new() }),
+        demangled: None,
+    }))
+}
+
+/// The first of `attrs` present on `entry` or the declarations it refers to.
+fn name<'a>(dwarf: &Dwarf<'a>, units: &[Unit<Reader<'a>>], unit: &Unit<Reader<'a>>, entry: &DebuggingInformationEntry<Reader<'a>>, attrs: &[DwAt]) -> Option<String> {
+    let (unit, value) = attrs.iter().find_map(|&a| inherited(units, unit, entry, a))?;
+    Some(dwarf.attr_string(unit, value).ok()?.to_string_lossy().into_owned())
+}
+
+/// `attr` of `entry`, or of the DIE it completes (`DW_AT_specification`) or
+/// is an instance of (`DW_AT_abstract_origin`), with the unit it was found in.
+fn inherited<'u, 'a>(
+    units: &'u [Unit<Reader<'a>>],
+    unit: &'u Unit<Reader<'a>>,
+    entry: &DebuggingInformationEntry<Reader<'a>>,
+    attr: DwAt,
+) -> Option<(&'u Unit<Reader<'a>>, AttributeValue<Reader<'a>>)> {
+    if let Ok(Some(v)) = entry.attr_value(attr) { return Some((unit, v)); }
+    let (mut unit, mut offset) = origin(units, unit, entry)?;
+    for _ in 0..MAX_ORIGIN_DEPTH {
+        let die = unit.entry(offset).ok()?;
+        if let Ok(Some(v)) = die.attr_value(attr) { return Some((unit, v)); }
+        (unit, offset) = origin(units, unit, &die)?;
+    }
+    None
+}
+
+/// The DIE `entry` refers to, in its own unit or (`DW_FORM_ref_addr`) any other.
+fn origin<'u, 'a>(
+    units: &'u [Unit<Reader<'a>>],
+    unit: &'u Unit<Reader<'a>>,
+    entry: &DebuggingInformationEntry<Reader<'a>>,
+) -> Option<(&'u Unit<Reader<'a>>, UnitOffset)> {
+    [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification].into_iter().find_map(|a| match entry.attr_value(a) {
+        Ok(Some(AttributeValue::UnitRef(offset))) => Some((unit, offset)),
+        Ok(Some(AttributeValue::DebugInfoRef(offset))) => unit_at(units, offset),
+        _ => None,
+    })
+}
+
+/// The unit holding section offset `offset`, and the offset within it.
+fn unit_at<'u, 'a>(units: &'u [Unit<Reader<'a>>], offset: DebugInfoOffset) -> Option<(&'u Unit<Reader<'a>>, UnitOffset)> {
+    let i = units.partition_point(|u| u.header.offset().as_debug_info_offset().is_some_and(|o| o <= offset));
+    let unit = units.get(i.checked_sub(1)?)?;
+    Some((unit, offset.to_unit_offset(&unit.header)?))
+}
+
+/// Unix or Windows (`C:\`, `\\server`) absolute path.
+fn is_absolute(path: &str) -> bool {
+    path.starts_with('/') || path.starts_with('\\') || path.as_bytes().get(1) == Some(&b':')
+}
+
+/// Path of line-table file `index`, joined with its include directory and,
+/// if that is relative too, the unit's compilation directory.
+fn file_name(dwarf: &Dwarf, unit: &Unit<Reader>, index: u64) -> Option<String> {
+    let header = unit.line_program.as_ref()?.header();
+    let file = header.file(index)?;
+    let mut path = dwarf.attr_string(unit, file.path_name()).ok()?.to_string_lossy().into_owned();
+    let dir = file.directory(header)
+        .and_then(|d| dwarf.attr_string(unit, d).ok())
+        .map(|d| d.to_string_lossy().into_owned());
+    let comp_dir = unit.comp_dir.map(|d| d.to_string_lossy().into_owned());
+    for dir in [dir, comp_dir].into_iter().flatten() {
+        if is_absolute(&path) { break; }
+        if !dir.is_empty() { path = format!("{}/{}", dir.trim_end_matches('/'), path); }
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cstr(s: &str) -> Vec<u8> {
+        s.bytes().chain([0]).collect()
+    }
+
+    /// DWARF 4 unit header (32-bit format, 8-byte addresses) around `dies`.
+    fn unit(dies: &[u8]) -> Vec<u8> {
+        let mut out = (7 + dies.len() as u32).to_le_bytes().to_vec();
+        out.extend(4u16.to_le_bytes());
+        out.extend(0u32.to_le_bytes());
+        out.push(8);
+        out.extend(dies);
+        out
+    }
+
+    /// DWARF 4 line program header with no opcodes.
+    fn line_program(dirs: &[&str], files: &[(&str, u8)]) -> Vec<u8> {
+        let mut header = vec![1, 1, 1, (-5i8) as u8, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+        for d in dirs { header.extend(cstr(d)); }
+        header.push(0);
+        for (f, dir) in files { header.extend(cstr(f)); header.extend([*dir, 0, 0]); }
+        header.push(0);
+        let mut out = (6 + header.len() as u32).to_le_bytes().to_vec();
+        out.extend(4u16.to_le_bytes());
+        out.extend((header.len() as u32).to_le_bytes());
+        out.extend(header);
+        out
+    }
+
+    fn root(name: &str, comp_dir: &str, stmt_list: u32) -> Vec<u8> {
+        let mut die = vec![1];
+        die.extend(cstr(name));
+        die.extend(cstr(comp_dir));
+        die.extend(stmt_list.to_le_bytes());
+        die.extend(0u64.to_le_bytes());
+        die
+    }
+
+    #[test]
+    fn functions_from_dwarf() {
+        use gimli::constants::*;
+        let abbrev: Vec<u8> = [
+            &[1, DW_TAG_compile_unit.0 as u8, 1][..],
+            &[DW_AT_name.0 as u8, DW_FORM_string.0 as u8, DW_AT_comp_dir.0 as u8, DW_FORM_string.0 as u8],
+            &[DW_AT_stmt_list.0 as u8, DW_FORM_sec_offset.0 as u8, DW_AT_low_pc.0 as u8, DW_FORM_addr.0 as u8, 0, 0],
+            &[2, DW_TAG_subprogram.0 as u8, 1],
+            &[DW_AT_name.0 as u8, DW_FORM_string.0 as u8, DW_AT_decl_file.0 as u8, DW_FORM_data1.0 as u8],
+            &[DW_AT_decl_line.0 as u8, DW_FORM_data1.0 as u8, DW_AT_ranges.0 as u8, DW_FORM_sec_offset.0 as u8, 0, 0],
+            &[3, DW_TAG_inlined_subroutine.0 as u8, 0],
+            &[DW_AT_abstract_origin.0 as u8, DW_FORM_ref_addr.0 as u8, DW_AT_low_pc.0 as u8, DW_FORM_addr.0 as u8],
+            &[DW_AT_high_pc.0 as u8, DW_FORM_data4.0 as u8, 0, 0],
+            &[4, DW_TAG_subprogram.0 as u8, 0],
+            &[DW_AT_abstract_origin.0 as u8, DW_FORM_ref_addr.0 as u8, DW_AT_low_pc.0 as u8, DW_FORM_addr.0 as u8],
+            &[DW_AT_high_pc.0 as u8, DW_FORM_data4.0 as u8, 0, 0],
+            &[5, DW_TAG_subprogram.0 as u8, 0],
+            &[DW_AT_name.0 as u8, DW_FORM_string.0 as u8, DW_AT_decl_file.0 as u8, DW_FORM_data1.0 as u8],
+            &[DW_AT_decl_line.0 as u8, DW_FORM_data1.0 as u8, DW_AT_inline.0 as u8, DW_FORM_data1.0 as u8, 0, 0],
+            &[0],
+        ].concat();
+
+        // Line tables: `src/a.c` relative to the unit's directory, and
+        // `lib/h.c` in the compilation directory (index 0).
+        let mut line = line_program(&["src"], &[("a.c", 1)]);
+        let line_b = line.len() as u32;
+        line.extend(line_program(&[], &[("lib/h.c", 0)]));
+
+        // Unit B declares the inline function `helper`.
+        let mut dies_b = root("b.c", "/other", line_b);
+        let helper = (11 + dies_b.len()) as u32;
+        dies_b.extend([&[5][..], &cstr("helper"), &[1, 3, DW_INL_inlined.0]].concat());
+        dies_b.push(0);
+        let mut info = unit(&dies_b);
+
+        // Unit A: `outer` split in two Thumb-style ranges (plus a discarded
+        // one) with `helper` inlined, and an out-of-line copy of `helper`.
+        let mut dies_a = root("a.c", "/work", 0);
+        dies_a.extend([&[2][..], &cstr("outer"), &[1, 7], &0u32.to_le_bytes()].concat());
+        dies_a.extend([&[3][..], &helper.to_le_bytes(), &0x1011u64.to_le_bytes(), &8u32.to_le_bytes()].concat());
+        dies_a.push(0);
+        dies_a.extend([&[4][..], &helper.to_le_bytes(), &0x3000u64.to_le_bytes(), &0x20u32.to_le_bytes()].concat());
+        dies_a.push(0);
+        info.extend(unit(&dies_a));
+
+        let ranges: Vec<u8> = [0x1001u64, 0x1041, 0, 0x10, 0x2001, 0x2011, 0, 0].iter().flat_map(|v| v.to_le_bytes()).collect();
+
+        let dwarf = gimli::Dwarf::load(|id| -> gimli::Result<Reader> {
+            let data: &[u8] = match id {
+                gimli::SectionId::DebugAbbrev => &abbrev,
+                gimli::SectionId::DebugInfo => &info,
+                gimli::SectionId::DebugLine => &line,
+                gimli::SectionId::DebugRanges => &ranges,
+                _ => &[],
+            };
+            Ok(EndianSlice::new(data, RunTimeEndian::Little))
+        }).unwrap();
+
+        let funs = dwarf_functions(&dwarf, !1);
+        assert_eq!(funs.len(), 2);
+        let outer = &funs[0];
+        assert_eq!((outer.name.as_str(), outer.start, outer.size), ("outer", 0x1000, 0x40));
+        assert_eq!(outer.fragments, [(0x2000, 0x2010)]);
+        let debug = outer.debug.as_ref().unwrap();
+        assert_eq!((debug.file.as_deref(), debug.line), (Some("/work/src/a.c"), Some(7)));
+        assert_eq!(debug.inlined, ["helper"]);
+
+        let copy = &funs[1];
+        assert_eq!((copy.name.as_str(), copy.start, copy.size), ("helper", 0x3000, 0x20));
+        let debug = copy.debug.as_ref().unwrap();
+        assert_eq!((debug.file.as_deref(), debug.line), (Some("/other/lib/h.c"), Some(3)));
+    }
+}
@@ -0,0 +1,47 @@
+//! Function ranges from the DWARF call frame information in `.eh_frame`.
+
+use gimli::{BaseAddresses, CieOrFde, EhFrame, EhFrameHdr, EndianSlice, Pointer, RunTimeEndian, UnwindSection};
+
+/// Where `.eh_frame` and the sections its pointer encodings are relative to
+/// were loaded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EhFrameBases {
+    pub eh_frame: u64,
+    pub text: Option<u64>,
+    pub got: Option<u64>,
+}
+
+fn endian(big_endian: bool) -> RunTimeEndian {
+    if big_endian { RunTimeEndian::Big } else { RunTimeEndian::Little }
+}
+
+/// Address of `.eh_frame` as recorded in an `.eh_frame_hdr` loaded at `addr`.
+pub fn eh_frame_from_hdr(data: &[u8], addr: u64, address_size: u8, big_endian: bool) -> Option<u64> {
+    let bases = BaseAddresses::default().set_eh_frame_hdr(addr);
+    let hdr = EhFrameHdr::new(data, endian(big_endian)).parse(&bases, address_size).ok()?;
+    match hdr.eh_frame_ptr() {
+        Pointer::Direct(p) => Some(p),
+        Pointer::Indirect(_) => None,
+    }
+}
+
+/// `[pc_begin, pc_end)` of every FDE in `data`, in section order. Parsing
+/// stops at the first malformed entry.
+pub fn fde_ranges(data: &[u8], bases: EhFrameBases, address_size: u8, big_endian: bool) -> Vec<(u64, u64)> {
+    let mut eh_frame = EhFrame::new(data, endian(big_endian));
+    eh_frame.set_address_size(address_size);
+    let mut gimli_bases = BaseAddresses::default().set_eh_frame(bases.eh_frame);
+    if let Some(text) = bases.text { gimli_bases = gimli_bases.set_text(text); }
+    if let Some(got) = bases.got { gimli_bases = gimli_bases.set_got(got); }
+
+    let mut out = Vec::new();
+    let mut entries = eh_frame.entries(&gimli_bases);
+    while let Ok(Some(entry)) = entries.next() {
+        let CieOrFde::Fde(partial) = entry else { continue };
+        let Ok(fde) = partial.parse(|section: &EhFrame<EndianSlice<RunTimeEndian>>, bases, offset| section.cie_from_offset(bases, offset)) else { continue };
+        if fde.len() > 0 {
+            out.push((fde.initial_address(), fde.initial_address().wrapping_add(fde.len())));
+        }
+    }
+    out
+}
@@ -0,0 +1,259 @@
+//! Go executables: functions from the runtime's pclntab, which `strip` and
+//! `-ldflags=-s` leave in place because the runtime needs it for stack
+//! traces and garbage collection.
+
+use super::FunctionSymbol;
+use goblin::{elf, mach::MachO, pe};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Version {
+    /// Go 1.2 to 1.15: one table, absolute entry addresses.
+    V12,
+    /// Go 1.16 and 1.17: separate name, file and pc tables.
+    V116,
+    /// Go 1.18 on: 32-bit entry offsets from the start of the text.
+    V118,
+}
+
+fn version(magic: u32) -> Option<Version> {
+    match magic {
+        0xffff_fffb => Some(Version::V12),
+        0xffff_fffa => Some(Version::V116),
+        0xffff_fff0 | 0xffff_fff1 => Some(Version::V118),
+        _ => None,
+    }
+}
+
+/// A pclntab in the target's byte order.
+struct Table<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+    ptr: usize,
+}
+
+impl Table<'_> {
+    fn uint(&self, off: usize, width: usize) -> Option<u64> {
+        let bytes = self.bytes.get(off..off.checked_add(width)?)?;
+        let mut v = 0u64;
+        for i in 0..width {
+            v = (v << 8) | bytes[if self.big_endian { i } else { width - 1 - i }] as u64;
+        }
+        Some(v)
+    }
+
+    /// The `k`th pointer-sized header field after the magic and sizes.
+    fn header(&self, k: usize) -> Option<usize> {
+        self.uint(8 + k * self.ptr, self.ptr).map(|v| v as usize)
+    }
+
+    fn c_string(&self, off: usize) -> Option<String> {
+        let bytes = self.bytes.get(off..)?;
+        let end = bytes.iter().position(|&b| b == 0)?;
+        Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+}
+
+/// Functions of the pclntab at the start of `bytes`, or `None` if it is not
+/// one. `text` stands in for a Go 1.18+ text start that is left to a dynamic
+/// relocation, as in PIE builds.
+pub fn parse(bytes: &[u8], text: u64) -> Option<Vec<FunctionSymbol>> {
+    let head = bytes.get(..8)?;
+    let magic = |big_endian: bool| {
+        let m: [u8; 4] = head[..4].try_into().unwrap();
+        version(if big_endian { u32::from_be_bytes(m) } else { u32::from_le_bytes(m) }).map(|v| (v, big_endian))
+    };
+    let (version, big_endian) = magic(false).or_else(|| magic(true))?;
+    // Two zero bytes, the instruction size quantum and the pointer size.
+    if head[4..6] != [0, 0] || !matches!(head[6], 1 | 2 | 4) || !matches!(head[7], 4 | 8) { return None; }
+    let t = Table { bytes, big_endian, ptr: head[7] as usize };
+
+    // Where the function table starts, the width of its (entry, func offset)
+    // pairs, and what func offsets, name offsets and entries are relative to.
+    let nfunc = t.header(0)?;
+    let (functab, field, funcs, names, base) = match version {
+        Version::V12 => (8 + t.ptr, t.ptr, 0, 0, 0),
+        Version::V116 => (t.header(6)?, t.ptr, t.header(6)?, t.header(2)?, 0),
+        Version::V118 => (t.header(7)?, 4, t.header(7)?, t.header(3)?, t.header(2).filter(|&s| s != 0).map_or(text, |s| s as u64)),
+    };
+    if nfunc == 0 || nfunc.checked_mul(2 * field)? > bytes.len() { return None; }
+
+    let mut funs = Vec::with_capacity(nfunc);
+    for i in 0..nfunc {
+        let pair = functab.checked_add(2 * i * field)?;
+        let start = base.checked_add(t.uint(pair, field)?)?;
+        // The next entry, or after the last one, the end of the text.
+        let end = base.checked_add(t.uint(pair + 2 * field, field)?)?;
+        if end < start { return None; }
+        let func = funcs.checked_add(t.uint(pair + field, field)? as usize)?;
+        // `_func` starts with the entry, then a signed offset of the name.
+        let name_off = t.uint(func.checked_add(field)?, 4)? as u32 as i32;
+        let name = t.c_string(names.checked_add_signed(name_off as isize)?)?;
+        if name.is_empty() || name == "go:buildid" || name == "go.buildid" { continue; }
+        funs.push(FunctionSymbol { name, start, size: end - start, fragments: Vec::new(), debug: None, demangled: None });
+    }
+    (!funs.is_empty()).then_some(funs)
+}
+
+/// The first pclntab found in a section the linker folded it into.
+fn scan(bytes: &[u8], text: u64) -> Option<Vec<FunctionSymbol>> {
+    (0..bytes.len().saturating_sub(8)).step_by(4)
+        .filter(|&off| version(u32::from_le_bytes(bytes[off..off + 4].try_into().unwrap())).is_some()
+            || version(u32::from_be_bytes(bytes[off..off + 4].try_into().unwrap())).is_some())
+        .find_map(|off| parse(&bytes[off..], text))
+}
+
+/// `.gopclntab`, or for PIE and externally linked builds, a table inside
+/// `.data.rel.ro` or `.rodata`.
+pub fn elf(elf: &elf::Elf, data: &[u8]) -> Vec<FunctionSymbol> {
+    let text = super::elf_section(elf, ".text").map_or(0, |s| s.sh_addr);
+    if let Some(sh) = super::elf_section(elf, ".gopclntab") {
+        return super::elf_section_data(data, sh).and_then(|b| parse(b, text)).unwrap_or_default();
+    }
+    if ![".note.go.buildid", ".go.buildinfo"].iter().any(|n| super::elf_section(elf, n).is_some()) { return Vec::new(); }
+    [".data.rel.ro", ".rodata"].iter()
+        .filter_map(|n| super::elf_section(elf, n))
+        .filter_map(|sh| super::elf_section_data(data, sh))
+        .find_map(|b| scan(b, text))
+        .unwrap_or_default()
+}
+
+/// `__gopclntab`, whichever segment holds it.
+pub fn macho(macho: &MachO, data: &[u8]) -> Vec<FunctionSymbol> {
+    let sections: Vec<_> = macho.segments.iter()
+        .filter_map(|s| s.sections().ok())
+        .flatten()
+        .map(|(section, _)| section)
+        .collect();
+    let text = sections.iter().find(|s| s.name().is_ok_and(|n| n == "__text")).map_or(0, |s| s.addr);
+    sections.iter()
+        .find(|s| s.name().is_ok_and(|n| n == "__gopclntab"))
+        .and_then(|s| data.get(s.offset as usize..(s.offset as usize).checked_add(s.size as usize)?))
+        .and_then(|b| parse(b, text))
+        .unwrap_or_default()
+}
+
+/// Go puts the table in `.rdata`; the text opens with the Go build ID.
+pub fn pe(pe: &pe::PE, data: &[u8]) -> Vec<FunctionSymbol> {
+    let section_data = |name: &str| {
+        let s = pe.sections.iter().find(|s| s.name().is_ok_and(|n| n == name))?;
+        let off = s.pointer_to_raw_data as usize;
+        Some((s, data.get(off..off.checked_add(s.size_of_raw_data as usize)?)?))
+    };
+    let Some((text, code)) = section_data(".text") else { return Vec::new() };
+    if !code.starts_with(b"\xff Go build ID: \"") { return Vec::new(); }
+    let text = pe.image_base as u64 + text.virtual_address as u64;
+    section_data(".rdata").and_then(|(_, b)| scan(b, text)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: u64 = 0x40_1000;
+
+    /// A little-endian 64-bit pclntab of `version` with `funcs` (name, entry)
+    /// and the text ending at `end`: header, function table, `_func`
+    /// records, then names.
+    fn table(version: Version, funcs: &[(&str, u64)], end: u64) -> Vec<u8> {
+        let magic: u32 = match version { Version::V12 => 0xffff_fffb, Version::V116 => 0xffff_fffa, Version::V118 => 0xffff_fff1 };
+        let field = if version == Version::V118 { 4 } else { 8 };
+        let n = funcs.len();
+        let functab = if version == Version::V12 { 16 } else { 8 + 8 * 8 };
+        let records = functab + (2 * n + 1) * field;
+        let names = records + 16 * n;
+        // What entries, `_func` offsets and name offsets are relative to.
+        let (entry_base, func_base, name_base) = match version {
+            Version::V12 => (0, 0, 0),
+            Version::V116 => (0, functab, names),
+            Version::V118 => (TEXT, functab, names),
+        };
+
+        let mut out = magic.to_le_bytes().to_vec();
+        out.extend([0, 0, 1, 8]);
+        let header: Vec<u64> = match version {
+            Version::V12 => vec![n as u64],
+            Version::V116 => vec![n as u64, 0, names as u64, 0, 0, 0, functab as u64, 0],
+            Version::V118 => vec![n as u64, 0, TEXT, names as u64, 0, 0, 0, functab as u64],
+        };
+        header.iter().for_each(|w| out.extend(w.to_le_bytes()));
+        let put = |out: &mut Vec<u8>, v: u64| out.extend(&v.to_le_bytes()[..field]);
+        for (i, &(_, entry)) in funcs.iter().enumerate() {
+            put(&mut out, entry - entry_base);
+            put(&mut out, (records + 16 * i - func_base) as u64);
+        }
+        put(&mut out, end - entry_base);
+        let mut name_table = Vec::new();
+        for &(name, entry) in funcs {
+            let mut record = Vec::new();
+            put(&mut record, entry - entry_base);
+            record.extend(((names + name_table.len() - name_base) as i32).to_le_bytes());
+            record.resize(16, 0);
+            out.extend(record);
+            name_table.extend(name.as_bytes());
+            name_table.push(0);
+        }
+        out.extend(name_table);
+        out
+    }
+
+    fn functions(bytes: &[u8], text: u64) -> Vec<(String, u64, u64)> {
+        parse(bytes, text).unwrap().into_iter().map(|f| (f.name, f.start, f.size)).collect()
+    }
+
+    fn expected() -> Vec<(String, u64, u64)> {
+        vec![("main.main".to_string(), TEXT, 0x40), ("main.init".to_string(), TEXT + 0x40, 0x20)]
+    }
+
+    const FUNCS: [(&str, u64); 3] = [("main.main", TEXT), ("go:buildid", TEXT + 0x40), ("main.init", TEXT + 0x40)];
+
+    #[test]
+    fn go_1_2() {
+        assert_eq!(functions(&table(Version::V12, &FUNCS, TEXT + 0x60), 0), expected());
+    }
+
+    #[test]
+    fn go_1_16() {
+        assert_eq!(functions(&table(Version::V116, &FUNCS, TEXT + 0x60), 0), expected());
+    }
+
+    #[test]
+    fn go_1_18() {
+        assert_eq!(functions(&table(Version::V118, &FUNCS, TEXT + 0x60), 0), expected());
+    }
+
+    #[test]
+    fn go_1_18_text_start_left_to_relocation() {
+        let mut bytes = table(Version::V118, &FUNCS, TEXT + 0x60);
+        bytes[24..32].fill(0);
+        assert_eq!(functions(&bytes, TEXT), expected());
+    }
+
+    #[test]
+    fn truncated_tables_are_rejected() {
+        for version in [Version::V12, Version::V116, Version::V118] {
+            let bytes = table(version, &FUNCS, TEXT + 0x60);
+            for len in 0..bytes.len() {
+                assert!(parse(&bytes[..len], 0).is_none(), "{:?} cut at {}", version, len);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_headers_are_rejected() {
+        let good = table(Version::V116, &FUNCS, TEXT + 0x60);
+        let corrupt = |at: usize, patch: &[u8]| {
+            let mut bytes = good.clone();
+            bytes[at..at + patch.len()].copy_from_slice(patch);
+            parse(&bytes, 0)
+        };
+        assert!(corrupt(0, &[0xfe, 0xff, 0xff, 0xff]).is_none(), "magic");
+        assert!(corrupt(4, &[1]).is_none(), "padding");
+        assert!(corrupt(6, &[3]).is_none(), "instruction size quantum");
+        assert!(corrupt(7, &[2]).is_none(), "pointer size");
+        assert!(corrupt(8, &u64::MAX.to_le_bytes()).is_none(), "function count");
+        assert!(corrupt(8 + 6 * 8, &u64::MAX.to_le_bytes()).is_none(), "function table offset");
+        assert!(corrupt(8 + 2 * 8, &u64::MAX.to_le_bytes()).is_none(), "name table offset");
+        // Entries out of order.
+        assert!(parse(&table(Version::V116, &[("a", TEXT + 0x10), ("b", TEXT)], TEXT + 0x20), 0).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+//! Imported functions by the addresses calls reach them through: ELF PLT
+//! stubs and GOT slots, PE import address table and delay-load slots.
+
+use super::Segment;
+use goblin::{elf, pe};
+use std::collections::BTreeMap;
+
+/// PLT header and entry sizes where stubs follow `.rela.plt` order.
+fn plt_layout(machine: u16) -> Option<(u64, u64)> {
+    match machine {
+        elf::header::EM_AARCH64 | elf::header::EM_RISCV => Some((32, 16)),
+        _ => None,
+    }
+}
+
+/// GOT slot an x86 PLT stub jumps through: `jmp *slot(%rip)` on x86_64,
+/// `jmp *slot` or, in PIC code, `jmp *slot(%ebx)` off `.got.plt` on IA-32.
+fn x86_stub_slot(stub: &[u8], addr: u64, is_64: bool, got: u64) -> impl Iterator<Item = u64> + '_ {
+    (0..stub.len().saturating_sub(5)).filter_map(move |i| {
+        let disp = i32::from_le_bytes(stub[i + 2..i + 6].try_into().unwrap());
+        match (stub[i], stub[i + 1], is_64) {
+            (0xff, 0x25, true) => Some((addr + i as u64 + 6).wrapping_add_signed(disp as i64)),
+            (0xff, 0x25, false) => Some(disp as u32 as u64),
+            (0xff, 0xa3, false) => Some(got.wrapping_add_signed(disp as i64)),
+            _ => None,
+        }
+    })
+}
+
+pub fn elf(elf: &elf::Elf, data: &[u8]) -> BTreeMap<u64, String> {
+    let mut out = BTreeMap::new();
+    let symbol = |index: usize| {
+        let sym = elf.dynsyms.get(index)?;
+        let name = elf.dynstrtab.get_at(sym.st_name).filter(|n| !n.is_empty())?;
+        Some((name.to_string(), sym.st_shndx == elf::section_header::SHN_UNDEF as usize))
+    };
+    // `.rela.plt` slots, in PLT order; other dynamic relocations only name
+    // slots of undefined symbols (`-fno-plt` calls, imported pointers).
+    let mut jump_slots = Vec::new();
+    for r in elf.pltrelocs.iter() {
+        let Some((name, _)) = symbol(r.r_sym) else { continue };
+        out.insert(r.r_offset, name.clone());
+        jump_slots.push(name);
+    }
+    for r in elf.dynrelas.iter().chain(elf.dynrels.iter()) {
+        if let Some((name, true)) = symbol(r.r_sym) {
+            out.entry(r.r_offset).or_insert(name);
+        }
+    }
+
+    let machine = elf.header.e_machine;
+    let mut stubs = Vec::new();
+    for sh in &elf.section_headers {
+        let name = elf.shdr_strtab.get_at(sh.sh_name).unwrap_or("");
+        if !matches!(name, ".plt" | ".plt.sec" | ".plt.got") { continue; }
+        let Some(bytes) = super::elf_section_data(data, sh) else { continue };
+        match machine {
+            elf::header::EM_X86_64 | elf::header::EM_386 => {
+                let got = super::elf_section(elf, ".got.plt").or_else(|| super::elf_section(elf, ".got")).map_or(0, |s| s.sh_addr);
+                let entsize = if sh.sh_entsize >= 8 { sh.sh_entsize as usize } else { 16 };
+                for (i, stub) in bytes.chunks(entsize).enumerate() {
+                    let addr = sh.sh_addr + (i * entsize) as u64;
+                    if let Some(name) = x86_stub_slot(stub, addr, elf.is_64, got).find_map(|slot| out.get(&slot)) {
+                        stubs.push((addr, name.clone()));
+                    }
+                }
+            }
+            _ if name == ".plt" => {
+                let Some((header, entsize)) = plt_layout(machine) else { continue };
+                if sh.sh_size != header + entsize * jump_slots.len() as u64 { continue; }
+                for (i, name) in jump_slots.iter().enumerate() {
+                    stubs.push((sh.sh_addr + header + entsize * i as u64, name.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+    out.extend(stubs);
+    out
+}
+
+/// IAT slots of ordinary and delay-loaded imports. Imports by ordinal are
+/// named `dll#ordinal`.
+pub fn pe(pe: &pe::PE, data: &[u8], segments: &[Segment]) -> BTreeMap<u64, String> {
+    let base = pe.image_base as u64;
+    let mut out = BTreeMap::new();
+    for imp in &pe.imports {
+        let name = if imp.name.starts_with("ORDINAL ") { format!("{}#{}", imp.dll, imp.ordinal) } else { imp.name.to_string() };
+        out.insert(base + imp.offset as u64, name);
+    }
+
+    let read = |rva: u64, len: usize| -> Option<&[u8]> {
+        let va = base + rva;
+        let seg = segments.iter().find(|s| s.contains(va))?;
+        let off = (seg.offset + va - seg.vaddr) as usize;
+        data.get(off..off.checked_add(len)?)
+    };
+    let word = |rva: u64, size: usize| -> Option<u64> {
+        let bytes = read(rva, size)?;
+        Some(if size == 8 { u64::from_le_bytes(bytes.try_into().ok()?) } else { u32::from_le_bytes(bytes.try_into().ok()?) as u64 })
+    };
+    let c_string = |rva: u64| -> Option<String> {
+        let va = base + rva;
+        let seg = segments.iter().find(|s| s.contains(va))?;
+        let bytes = data.get((seg.offset + va - seg.vaddr) as usize..)?;
+        let end = bytes.iter().position(|&b| b == 0)?;
+        Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    };
+
+    // IMAGE_DELAYLOAD_DESCRIPTOR: attributes, DLL name, module handle, IAT,
+    // name table, ... The original (attributes bit 0 clear) format stored
+    // VAs instead of RVAs.
+    let Some(dir) = pe.header.optional_header.as_ref().and_then(|oh| oh.data_directories.get_delay_import_descriptor()) else { return out };
+    let ptr = if pe.is_64 { 8 } else { 4 };
+    let ordinal_flag = 1u64 << (ptr * 8 - 1);
+    for desc in (dir.virtual_address as u64..).step_by(32).take(dir.size as usize / 32) {
+        let Some(fields) = (0..5).map(|i| word(desc + i * 4, 4)).collect::<Option<Vec<_>>>() else { break };
+        let rva = |v: u64| if fields[0] & 1 != 0 { v } else { v.wrapping_sub(base) };
+        if fields[1] == 0 { break; }
+        let dll = c_string(rva(fields[1])).unwrap_or_default();
+        let (iat, names) = (rva(fields[3]), rva(fields[4]));
+        for i in 0u64.. {
+            let Some(entry) = word(names + i * ptr as u64, ptr).filter(|&e| e != 0) else { break };
+            let name = if entry & ordinal_flag != 0 {
+                format!("{}#{}", dll, entry & 0xffff)
+            } else {
+                // IMAGE_IMPORT_BY_NAME: a 2-byte hint, then the name.
+                c_string(rva(entry) + 2).unwrap_or_default()
+            };
+            out.insert(base + iat + i * ptr as u64, name);
+        }
+    }
+    out
+}
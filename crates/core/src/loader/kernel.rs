@@ -0,0 +1,183 @@
+//! Linux kernel images (`vmlinux`) and modules: instruction sites the kernel
+//! rewrites at boot or run time.
+//!
+//! Alternatives, static keys (jump labels) and ftrace call sites hold
+//! placeholder code whose bytes depend on the build configuration rather than
+//! the source, so the disassembler replaces each one with a single marker.
+
+use super::BinaryImage;
+use crate::arch::{Architecture, Endianness};
+use goblin::elf;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PatchKind {
+    /// `ALTERNATIVE()` original instructions, replaced per CPU feature.
+    Alternative,
+    /// Static key NOP/jump to the out-of-line branch at `target`.
+    StaticKey { target: u64 },
+    /// `__fentry__`/`mcount` call or its patchable NOPs.
+    Ftrace,
+}
+
+/// A runtime-patched site; `len` 0 means the single instruction at `addr`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PatchSite {
+    pub addr: u64,
+    pub len: u64,
+    pub kind: PatchKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Alternatives,
+    JumpLabels,
+    Mcount,
+}
+
+impl Table {
+    const ALL: [Table; 3] = [Table::Alternatives, Table::JumpLabels, Table::Mcount];
+
+    /// The table's section in objects and modules.
+    pub fn from_section(name: &str) -> Option<Self> {
+        match name {
+            ".altinstructions" => Some(Table::Alternatives),
+            "__jump_table" => Some(Table::JumpLabels),
+            "__mcount_loc" | "__patchable_function_entries" => Some(Table::Mcount),
+            _ => None,
+        }
+    }
+
+    /// Linker symbols around the table in `vmlinux`, where it is folded
+    /// into a larger output section.
+    fn bounds(self) -> (&'static str, &'static str) {
+        match self {
+            Table::Alternatives => ("__alt_instructions", "__alt_instructions_end"),
+            Table::JumpLabels => ("__start___jump_table", "__stop___jump_table"),
+            Table::Mcount => ("__start_mcount_loc", "__stop_mcount_loc"),
+        }
+    }
+}
+
+/// `[start, end)` of each table in a linked kernel, from its bounding
+/// symbols or else a section of its own.
+pub fn elf_tables(elf: &elf::Elf) -> Vec<(Table, u64, u64)> {
+    let mut symbols = BTreeMap::new();
+    for sym in elf.syms.iter() {
+        let Some(name) = elf.strtab.get_at(sym.st_name) else { continue };
+        if name.starts_with("__") && Table::ALL.iter().any(|t| t.bounds().0 == name || t.bounds().1 == name) {
+            symbols.insert(name, sym.st_value);
+        }
+    }
+    let mut out = Vec::new();
+    for table in Table::ALL {
+        let (start, stop) = table.bounds();
+        if let (Some(&s), Some(&e)) = (symbols.get(start), symbols.get(stop)) {
+            if s < e { out.push((table, s, e)); }
+            continue;
+        }
+        for sh in &elf.section_headers {
+            let name = elf.shdr_strtab.get_at(sh.sh_name).unwrap_or("");
+            if Table::from_section(name) == Some(table) && sh.sh_addr != 0 {
+                out.push((table, sh.sh_addr, sh.sh_addr + sh.sh_size));
+            }
+        }
+    }
+    out
+}
+
+/// Read a `width`-byte word in the image's byte order.
+fn word(bin: &BinaryImage, addr: u64, width: usize) -> Option<u64> {
+    let bytes = bin.read_va(addr, width).filter(|b| b.len() == width)?;
+    let mut v = 0u64;
+    for i in 0..width {
+        let b = bytes[if bin.endianness == Endianness::Little { width - 1 - i } else { i }];
+        v = (v << 8) | b as u64;
+    }
+    Some(v)
+}
+
+/// Address a self-relative `s32` at `addr` points to.
+fn rel32(bin: &BinaryImage, addr: u64) -> Option<u64> {
+    word(bin, addr, 4).map(|v| addr.wrapping_add_signed(v as u32 as i32 as i64))
+}
+
+/// `struct alt_instr` layouts: entry size and offset of the original length,
+/// which is a `u16` on RISC-V. x86 went from 13 to 12 bytes when `padlen`
+/// was dropped and to 14 when `cpuid` grew into `ft_flags`.
+fn alt_layouts(arch: Architecture) -> &'static [(u64, u64)] {
+    match arch {
+        Architecture::X86 | Architecture::X86_64 => &[(14, 12), (12, 10), (13, 10)],
+        Architecture::AArch64 => &[(12, 10)],
+        Architecture::RiscV32 | Architecture::RiscV64 => &[(16, 10)],
+        _ => &[],
+    }
+}
+
+fn alternatives(bin: &BinaryImage, start: u64, end: u64, out: &mut Vec<PatchSite>) {
+    let wide_len = matches!(bin.arch, Architecture::RiscV32 | Architecture::RiscV64);
+    let size = end - start;
+    // The right layout decodes every entry into a code address.
+    let decode = |&(entsize, len_at): &(u64, u64)| -> Option<Vec<PatchSite>> {
+        if !size.is_multiple_of(entsize) { return None; }
+        (0..size / entsize).map(|i| {
+            let entry = start + i * entsize;
+            let addr = rel32(bin, entry)?;
+            let len = word(bin, entry + len_at, if wide_len { 2 } else { 1 })?;
+            (bin.is_executable(addr) && len > 0 && len < 256)
+                .then_some(PatchSite { addr, len, kind: PatchKind::Alternative })
+        }).collect()
+    };
+    if let Some(sites) = alt_layouts(bin.arch).iter().find_map(decode) {
+        out.extend(sites);
+    }
+}
+
+fn jump_labels(bin: &BinaryImage, start: u64, end: u64, out: &mut Vec<PatchSite>) {
+    use Architecture::*;
+    let ptr = bin.bits as u64 / 8;
+    // `struct jump_entry`: code, target and key, as `s32` offsets from each
+    // field on architectures with relative jump labels.
+    let relative = matches!(bin.arch, X86 | X86_64 | AArch64 | RiscV32 | RiscV64 | PowerPc | PowerPc64 | S390x);
+    let entsize = if relative { 8 + ptr } else { 3 * ptr };
+    let mut entry = start;
+    while entry + entsize <= end {
+        let site = if relative {
+            rel32(bin, entry).zip(rel32(bin, entry + 4))
+        } else {
+            word(bin, entry, ptr as usize).zip(word(bin, entry + ptr, ptr as usize))
+        };
+        if let Some((addr, target)) = site.filter(|&(a, _)| bin.is_executable(a)) {
+            out.push(PatchSite { addr, len: 0, kind: PatchKind::StaticKey { target } });
+        }
+        entry += entsize;
+    }
+}
+
+fn mcount(bin: &BinaryImage, start: u64, end: u64, out: &mut Vec<PatchSite>) {
+    let ptr = bin.bits as u64 / 8;
+    let mut entry = start;
+    while entry + ptr <= end {
+        if let Some(addr) = word(bin, entry, ptr as usize).filter(|&a| bin.is_executable(a)) {
+            out.push(PatchSite { addr, len: 0, kind: PatchKind::Ftrace });
+        }
+        entry += ptr;
+    }
+}
+
+/// Decode the patch tables of a kernel image or module, sorted by address.
+/// Where sites overlap, as with `ALTERNATIVE_2`, the longest one is kept.
+pub fn patch_sites(bin: &BinaryImage, tables: &[(Table, u64, u64)]) -> Vec<PatchSite> {
+    let mut out = Vec::new();
+    for &(table, start, end) in tables {
+        match table {
+            Table::Alternatives => alternatives(bin, start, end, &mut out),
+            Table::JumpLabels => jump_labels(bin, start, end, &mut out),
+            Table::Mcount => mcount(bin, start, end, &mut out),
+        }
+    }
+    out.sort_by_key(|s| (s.addr, std::cmp::Reverse(s.len)));
+    out.dedup_by_key(|s| s.addr);
+    out
+}
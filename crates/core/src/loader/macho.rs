@@ -0,0 +1,188 @@
+//! Mach-O images: segments, symbols and `LC_FUNCTION_STARTS`, and picking a
+//! slice out of a universal (fat) binary.
+
+use super::{FunctionSymbol, MappingKind, MappingSymbol, Segment, synthetic_name};
+use crate::arch::{Architecture, TargetArch};
+use anyhow::{Result, bail};
+use goblin::mach::constants::{S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS, VM_PROT_EXECUTE, VM_PROT_READ, VM_PROT_WRITE};
+use goblin::mach::load_command::CommandVariant;
+use goblin::mach::symbols::{N_SECT, N_STAB, N_TYPE, Nlist};
+use goblin::mach::{MachO, MultiArch, SingleArch};
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// `n_desc` flag marking a Thumb function on 32-bit ARM.
+const N_ARM_THUMB_DEF: u16 = 0x0008;
+
+/// The slice of `fat` built for `arch` and its byte range in the file.
+/// Without a choice, a binary with a single slice yields that one; otherwise
+/// the caller has to pick. `arm64` and `arm64e` (or `x86_64` and `x86_64h`)
+/// are different slices.
+pub fn select_slice<'a>(fat: &MultiArch<'a>, arch: Option<TargetArch>) -> Result<(MachO<'a>, Range<usize>)> {
+    let arches = fat.arches()?;
+    let targets: Vec<TargetArch> = arches.iter().map(|a| TargetArch::from_macho(a.cputype, a.cpusubtype)).collect();
+    let available = || {
+        let mut names: Vec<String> = Vec::new();
+        for name in targets.iter().map(|t| t.to_string()) {
+            if !names.contains(&name) { names.push(name); }
+        }
+        names.join(", ")
+    };
+    let index = match arch {
+        Some(want) => match targets.iter().enumerate().filter(|(_, t)| **t == want).map(|(i, _)| i).collect::<Vec<_>>()[..] {
+            [i] => i,
+            [] => bail!("Universal binary has no {} slice (has {})", want, available()),
+            ref all => bail!("Universal binary has {} {} slices; which one to diff is ambiguous", all.len(), want),
+        },
+        None if arches.len() == 1 => 0,
+        None => bail!("Universal binary with slices {}; choose one with --arch", available()),
+    };
+    match fat.get(index)? {
+        SingleArch::MachO(macho) => {
+            let start = arches[index].offset as usize;
+            Ok((macho, start..start.saturating_add(arches[index].size as usize)))
+        }
+        SingleArch::Archive(_) => bail!("Universal static libraries are not supported"),
+    }
+}
+
+/// A thin binary is its only slice, so a requested `arch` has to be that one.
+pub fn check_thin(macho: &MachO, arch: Option<TargetArch>) -> Result<()> {
+    let have = TargetArch::from_macho(macho.header.cputype, macho.header.cpusubtype);
+    match arch {
+        Some(want) if want != have => bail!("Mach-O binary is {}, not {}", have, want),
+        _ => Ok(()),
+    }
+}
+
+pub fn segments(macho: &MachO) -> Vec<Segment> {
+    macho.segments.iter()
+        // `__PAGEZERO` reserves address space with no access at all.
+        .filter(|s| s.vmsize > 0 && s.initprot != 0)
+        .map(|s| Segment {
+            name: s.name().unwrap_or("").to_string(),
+            vaddr: s.vmaddr,
+            vsize: s.vmsize,
+            offset: s.fileoff,
+            file_size: s.filesize.min(s.vmsize),
+            read: s.initprot & VM_PROT_READ != 0,
+            write: s.initprot & VM_PROT_WRITE != 0,
+            exec: s.initprot & VM_PROT_EXECUTE != 0,
+            member: None,
+        })
+        .collect()
+}
+
+/// `[start, end)` of every section holding instructions, by 1-based section
+/// number as symbols refer to them.
+fn code_sections(macho: &MachO) -> Vec<Option<(u64, u64)>> {
+    let mut out = Vec::new();
+    for segment in macho.segments.iter() {
+        let Ok(sections) = segment.sections() else { continue };
+        for (section, _) in sections {
+            let code = section.flags & (S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS) != 0;
+            out.push(code.then_some((section.addr, section.addr + section.size)));
+        }
+    }
+    out
+}
+
+/// Whether `nlist` is a defined symbol inside one of `sections`. Linker
+/// symbols such as `__mh_execute_header` name `__text` but lie before it.
+fn is_code_symbol(sections: &[Option<(u64, u64)>], nlist: &Nlist) -> bool {
+    nlist.n_type & N_STAB == 0
+        && nlist.n_type & N_TYPE == N_SECT
+        && nlist.n_sect.checked_sub(1)
+            .and_then(|i| *sections.get(i)?)
+            .is_some_and(|(start, end)| nlist.n_value >= start && nlist.n_value < end)
+}
+
+/// Addresses from `LC_FUNCTION_STARTS`: ULEB128 deltas from the start of
+/// `__TEXT`, ending at a zero delta. Thumb functions have bit 0 set.
+fn function_starts(macho: &MachO, data: &[u8]) -> Vec<u64> {
+    let Some(cmd) = macho.load_commands.iter().find_map(|lc| match &lc.command {
+        CommandVariant::FunctionStarts(cmd) => Some(cmd),
+        _ => None,
+    }) else { return Vec::new() };
+    let Some(text) = macho.segments.iter().find(|s| s.name().is_ok_and(|n| n == "__TEXT")) else { return Vec::new() };
+    let Some(bytes) = data.get(cmd.dataoff as usize..(cmd.dataoff as usize).saturating_add(cmd.datasize as usize)) else { return Vec::new() };
+
+    let mut out = Vec::new();
+    let mut addr = text.vmaddr;
+    let (mut delta, mut shift) = (0u64, 0u32);
+    for &b in bytes {
+        if shift < 64 { delta |= ((b & 0x7f) as u64) << shift; }
+        shift += 7;
+        if b & 0x80 != 0 { continue; }
+        if delta == 0 { break; }
+        addr = addr.wrapping_add(delta);
+        out.push(addr);
+        (delta, shift) = (0, 0);
+    }
+    out
+}
+
+/// Defined symbols in code sections, `LC_FUNCTION_STARTS` and the entry
+/// point. Mach-O symbols carry no size; the disassembler bounds them.
+pub fn functions(macho: &MachO, data: &[u8], arch: Architecture) -> Vec<FunctionSymbol> {
+    let sections = code_sections(macho);
+    let mut funs = Vec::new();
+    let mut named = HashSet::new();
+    for (name, nlist) in macho.symbols().flatten() {
+        if !is_code_symbol(&sections, &nlist) || !named.insert((nlist.n_value, name)) { continue; }
+        funs.push(FunctionSymbol { name: name.to_string(), start: nlist.n_value, size: 0, fragments: Vec::new(), debug: None, demangled: None });
+    }
+
+    let addr_mask = if arch == Architecture::Arm { !1 } else { !0 };
+    let mut known: HashSet<u64> = funs.iter().map(|f| f.start).collect();
+    let seeds = function_starts(macho, data).into_iter().chain((macho.entry != 0).then_some(macho.entry));
+    for start in seeds.map(|a| a & addr_mask) {
+        let in_code = sections.iter().flatten().any(|&(s, e)| start >= s && start < e);
+        if !in_code || !known.insert(start) { continue; }
+        funs.push(FunctionSymbol { name: synthetic_name(start), start, size: 0, fragments: Vec::new(), debug: None, demangled: None });
+    }
+    funs
+}
+
+/// ARM/Thumb state of each function on 32-bit ARM, from `N_ARM_THUMB_DEF`
+/// and the Thumb bit of function starts.
+pub fn mapping_symbols(macho: &MachO, data: &[u8], arch: Architecture) -> Vec<MappingSymbol> {
+    if arch != Architecture::Arm { return Vec::new(); }
+    let sections = code_sections(macho);
+    let kind = |thumb: bool| if thumb { MappingKind::Thumb } else { MappingKind::Arm };
+    let mut out: Vec<MappingSymbol> = macho.symbols().flatten()
+        .filter(|(_, n)| is_code_symbol(&sections, n))
+        .map(|(_, n)| MappingSymbol { addr: n.n_value, kind: kind(n.n_desc & N_ARM_THUMB_DEF != 0) })
+        .collect();
+    out.extend(function_starts(macho, data).into_iter().map(|a| MappingSymbol { addr: a & !1, kind: kind(a & 1 != 0) }));
+    out.sort_by_key(|m| m.addr);
+    out.dedup_by_key(|m| m.addr);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use goblin::mach::cputype::{CPU_SUBTYPE_ARM64_ALL, CPU_SUBTYPE_ARM64_E, CPU_TYPE_ARM64};
+
+    /// `mach_header_64` of an executable with no load commands.
+    fn header(cputype: u32, cpusubtype: u32) -> Vec<u8> {
+        [0xfeed_facfu32, cputype, cpusubtype, 2, 0, 0, 0, 0].iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn thin_binary_must_match_the_requested_arch() {
+        let arm64 = header(CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL);
+        let arm64 = MachO::parse(&arm64, 0).unwrap();
+        assert!(check_thin(&arm64, None).is_ok());
+        assert!(check_thin(&arm64, Some("arm64".parse().unwrap())).is_ok());
+        let err = check_thin(&arm64, Some("x86_64".parse().unwrap())).unwrap_err();
+        assert_eq!(err.to_string(), "Mach-O binary is aarch64, not x86_64");
+        assert!(check_thin(&arm64, Some("arm64e".parse().unwrap())).is_err());
+
+        let arm64e = header(CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_E | 0x8000_0000);
+        let arm64e = MachO::parse(&arm64e, 0).unwrap();
+        assert!(check_thin(&arm64e, Some("arm64e".parse().unwrap())).is_ok());
+        assert!(check_thin(&arm64e, Some("arm64".parse().unwrap())).is_err());
+    }
+}
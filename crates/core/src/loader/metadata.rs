@@ -0,0 +1,227 @@
+//! What an image says about itself beyond its code: entry point, build ID,
+//! sections, linked libraries and exploit mitigations.
+
+use super::Segment;
+use goblin::mach::MachO;
+use goblin::mach::load_command::CommandVariant;
+use goblin::{elf, pe};
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+    /// `readelf`-style letters for ELF (`WAX`, ...), `rwx` permissions for
+    /// PE/COFF and raw images, the raw flags word for Mach-O.
+    pub flags: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    pub entry: Option<u64>,
+    /// GNU build ID, PDB GUID and age, or Mach-O `LC_UUID`, in hex.
+    pub build_id: Option<String>,
+    pub sections: Vec<Section>,
+    /// Shared libraries loaded with the image, in load order.
+    pub libraries: Vec<String>,
+    /// Mitigations by name (`pie`, `nx`, `relro`, ...): `yes`, `no`, or a
+    /// level such as `partial`.
+    pub hardening: BTreeMap<String, String>,
+}
+
+fn yes_no(b: bool) -> String {
+    if b { "yes" } else { "no" }.to_string()
+}
+
+pub(crate) fn rwx(read: bool, write: bool, exec: bool) -> String {
+    format!("{}{}{}", if read { 'r' } else { '-' }, if write { 'w' } else { '-' }, if exec { 'x' } else { '-' })
+}
+
+/// `sh_flags` as the letters `readelf -S` prints.
+pub(crate) fn elf_section_flags(flags: u64) -> String {
+    use elf::section_header::*;
+    [
+        (SHF_WRITE, 'W'), (SHF_ALLOC, 'A'), (SHF_EXECINSTR, 'X'), (SHF_MERGE, 'M'),
+        (SHF_STRINGS, 'S'), (SHF_INFO_LINK, 'I'), (SHF_LINK_ORDER, 'L'), (SHF_OS_NONCONFORMING, 'O'),
+        (SHF_GROUP, 'G'), (SHF_TLS, 'T'), (SHF_COMPRESSED, 'C'), (SHF_EXCLUDE, 'E'),
+    ].into_iter().filter(|&(bit, _)| flags & bit as u64 != 0).map(|(_, c)| c).collect()
+}
+
+pub(crate) fn coff_section_flags(characteristics: u32) -> String {
+    use pe::section_table::*;
+    rwx(characteristics & IMAGE_SCN_MEM_READ != 0, characteristics & IMAGE_SCN_MEM_WRITE != 0, characteristics & IMAGE_SCN_MEM_EXECUTE != 0)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `GNU_PROPERTY_*_FEATURE_1_AND` bits from `NT_GNU_PROPERTY_TYPE_0` notes,
+/// where x86 records IBT/SHSTK and AArch64 BTI/PAC.
+fn gnu_feature_1(elf: &elf::Elf, data: &[u8], pr_type: u32) -> u32 {
+    const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+    let align = if elf.is_64 { 8 } else { 4 };
+    let read = |b: &[u8], at: usize| -> Option<u32> {
+        let w: [u8; 4] = b.get(at..at + 4)?.try_into().ok()?;
+        Some(if elf.little_endian { u32::from_le_bytes(w) } else { u32::from_be_bytes(w) })
+    };
+    let notes = elf.iter_note_headers(data).into_iter().flatten()
+        .chain(elf.iter_note_sections(data, Some(".note.gnu.property")).into_iter().flatten());
+    for note in notes.flatten() {
+        if note.n_type != NT_GNU_PROPERTY_TYPE_0 || note.name.trim_end_matches('\0') != "GNU" { continue; }
+        let mut at = 0;
+        while let (Some(ty), Some(size)) = (read(note.desc, at), read(note.desc, at + 4)) {
+            if ty == pr_type { return read(note.desc, at + 8).unwrap_or(0); }
+            at += (8 + size as usize).div_ceil(align) * align;
+        }
+    }
+    0
+}
+
+pub fn elf(elf: &elf::Elf, data: &[u8]) -> Metadata {
+    use elf::dynamic::{DF_1_NOW, DF_1_PIE, DF_BIND_NOW, DT_BIND_NOW};
+    use elf::program_header::{PF_X, PT_GNU_RELRO, PT_GNU_STACK, PT_INTERP};
+    let sections = elf.section_headers.iter().skip(1)
+        .map(|sh| Section {
+            name: elf.shdr_strtab.get_at(sh.sh_name).unwrap_or("").to_string(),
+            addr: sh.sh_addr,
+            size: sh.sh_size,
+            flags: elf_section_flags(sh.sh_flags),
+        })
+        .collect();
+
+    let mut hardening = BTreeMap::new();
+    let segment = |ty| elf.program_headers.iter().find(|ph| ph.p_type == ty);
+    let (flags, flags_1) = elf.dynamic.as_ref().map_or((0, 0), |d| (d.info.flags, d.info.flags_1));
+    let pie = if elf.header.e_type != elf::header::ET_DYN {
+        "no"
+    } else if segment(PT_INTERP).is_some() || flags_1 & DF_1_PIE != 0 {
+        "yes"
+    } else {
+        // A shared library; position independent by construction.
+        "dso"
+    };
+    hardening.insert("pie".to_string(), pie.to_string());
+    // Without `PT_GNU_STACK` the stack defaults to executable.
+    hardening.insert("nx".to_string(), yes_no(segment(PT_GNU_STACK).is_some_and(|ph| ph.p_flags & PF_X == 0)));
+    let bind_now = flags & DF_BIND_NOW != 0 || flags_1 & DF_1_NOW != 0
+        || elf.dynamic.as_ref().is_some_and(|d| d.dyns.iter().any(|d| d.d_tag == DT_BIND_NOW));
+    let relro = match (segment(PT_GNU_RELRO).is_some(), bind_now) {
+        (false, _) => "no",
+        (true, false) => "partial",
+        (true, true) => "full",
+    };
+    hardening.insert("relro".to_string(), relro.to_string());
+
+    let symbols = elf.dynsyms.iter().filter_map(|s| elf.dynstrtab.get_at(s.st_name))
+        .chain(elf.syms.iter().filter_map(|s| elf.strtab.get_at(s.st_name)));
+    let (mut canary, mut fortify) = (false, false);
+    for name in symbols {
+        canary |= matches!(name, "__stack_chk_fail" | "__stack_chk_guard");
+        fortify |= name.starts_with("__") && name.ends_with("_chk") && name != "__stack_chk_fail";
+    }
+    hardening.insert("canary".to_string(), yes_no(canary));
+    hardening.insert("fortify".to_string(), yes_no(fortify));
+    match elf.header.e_machine {
+        elf::header::EM_X86_64 | elf::header::EM_386 => {
+            let features = gnu_feature_1(elf, data, 0xc000_0002);
+            hardening.insert("ibt".to_string(), yes_no(features & 1 != 0));
+            hardening.insert("shstk".to_string(), yes_no(features & 2 != 0));
+        }
+        elf::header::EM_AARCH64 => {
+            let features = gnu_feature_1(elf, data, 0xc000_0000);
+            hardening.insert("bti".to_string(), yes_no(features & 1 != 0));
+            hardening.insert("pac".to_string(), yes_no(features & 2 != 0));
+        }
+        _ => {}
+    }
+
+    Metadata {
+        entry: (elf.entry != 0).then_some(elf.entry),
+        build_id: super::debug_file::build_id(elf, data).map(|id| hex(&id)),
+        sections,
+        libraries: elf.libraries.iter().map(|l| l.to_string()).collect(),
+        hardening,
+    }
+}
+
+pub fn pe(pe: &pe::PE, data: &[u8], segments: &[Segment]) -> Metadata {
+    use pe::dll_characteristic::*;
+    let base = pe.image_base as u64;
+    let sections = pe.sections.iter()
+        .map(|s| Section {
+            name: s.name().unwrap_or("").to_string(),
+            addr: base + s.virtual_address as u64,
+            size: s.virtual_size as u64,
+            flags: coff_section_flags(s.characteristics),
+        })
+        .collect();
+
+    let mut hardening = BTreeMap::new();
+    let oh = pe.header.optional_header.as_ref();
+    let chars = oh.map_or(0, |oh| oh.windows_fields.dll_characteristics);
+    for (name, flag) in [
+        ("dynamicbase", IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE),
+        ("highentropyva", IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA),
+        ("nx", IMAGE_DLLCHARACTERISTICS_NX_COMPAT),
+        ("cfg", IMAGE_DLLCHARACTERISTICS_GUARD_CF),
+    ] {
+        hardening.insert(name.to_string(), yes_no(chars & flag != 0));
+    }
+    // `/GS` code checks the cookie the load config points the loader at.
+    let cookie = oh.and_then(|oh| oh.data_directories.get_load_config_table()).and_then(|dir| {
+        let (ptr, at) = if pe.is_64 { (8, 0x58) } else { (4, 0x3c) };
+        if (dir.size as usize) < at + ptr { return None; }
+        let va = base + dir.virtual_address as u64 + at as u64;
+        let seg = segments.iter().find(|s| s.contains(va))?;
+        let off = (seg.offset + va - seg.vaddr) as usize;
+        Some(data.get(off..off + ptr)?.iter().any(|&b| b != 0))
+    });
+    hardening.insert("gs".to_string(), yes_no(cookie.unwrap_or(false)));
+
+    Metadata {
+        entry: (pe.entry != 0).then_some(base + pe.entry as u64),
+        build_id: super::pdb::PdbId::from_pe(pe).map(|id| id.symstore_key()),
+        sections,
+        libraries: pe.libraries.iter().map(|l| l.to_string()).collect(),
+        hardening,
+    }
+}
+
+pub fn macho(macho: &MachO) -> Metadata {
+    use goblin::mach::constants::cputype::{CPU_SUBTYPE_ARM64_E, CPU_TYPE_ARM64};
+    use goblin::mach::header::{MH_ALLOW_STACK_EXECUTION, MH_PIE};
+    let mut sections = Vec::new();
+    for segment in macho.segments.iter() {
+        let Ok(list) = segment.sections() else { continue };
+        for (s, _) in list {
+            let name = format!("{},{}", s.segname().unwrap_or(""), s.name().unwrap_or(""));
+            sections.push(Section { name, addr: s.addr, size: s.size, flags: format!("0x{:x}", s.flags) });
+        }
+    }
+    let build_id = macho.load_commands.iter().find_map(|lc| match &lc.command {
+        CommandVariant::Uuid(cmd) => Some(hex(&cmd.uuid)),
+        _ => None,
+    });
+
+    let mut hardening = BTreeMap::new();
+    hardening.insert("pie".to_string(), yes_no(macho.header.flags & MH_PIE != 0));
+    hardening.insert("nx".to_string(), yes_no(macho.header.flags & MH_ALLOW_STACK_EXECUTION == 0));
+    let canary = macho.symbols().flatten().any(|(name, _)| matches!(name, "___stack_chk_fail" | "___stack_chk_guard"));
+    hardening.insert("canary".to_string(), yes_no(canary));
+    if macho.header.cputype == CPU_TYPE_ARM64 {
+        // arm64e signs return addresses and code pointers.
+        hardening.insert("pac".to_string(), yes_no(macho.header.cpusubtype & 0xff == CPU_SUBTYPE_ARM64_E));
+    }
+
+    Metadata {
+        entry: (macho.entry != 0).then_some(macho.entry),
+        build_id,
+        sections,
+        // The first entry is the image itself.
+        libraries: macho.libs.iter().skip(1).map(|l| l.to_string()).collect(),
+        hardening,
+    }
+}
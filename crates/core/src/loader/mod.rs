@@ -1,91 +1,821 @@
+pub mod debug_file;
+pub mod demangle;
+pub mod dwarf;
+pub mod eh_frame;
+pub mod golang;
+pub mod imports;
+pub mod kernel;
+pub mod macho;
+pub mod metadata;
+pub mod object;
+pub mod pdb;
+pub mod raw;
+
+use crate::arch::{Abi, Architecture, Endianness, TargetArch};
 use anyhow::{Result, bail};
 use goblin::{Object, elf, pe};
 use memmap2::Mmap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::path::Path;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionSymbol {
     pub name: String,
+    /// Virtual address of the first instruction.
     pub start: u64,
     pub size: u64,
+    /// Detached `[start, end)` parts of the body, such as GCC's `.cold` splits.
+    #[serde(default)]
+    pub fragments: Vec<(u64, u64)>,
+    /// Source-level details, when debug information covers the function.
+    #[serde(default)]
+    pub debug: Option<DebugInfo>,
+    /// Readable form of a mangled `name`.
+    #[serde(default)]
+    pub demangled: Option<String>,
+}
+
+/// What DWARF says about a function beyond its name and bounds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebugInfo {
+    /// Declaring source file, joined with its compilation directory.
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    /// Functions inlined into this one, outermost first, without repeats.
+    #[serde(default)]
+    pub inlined: Vec<String>,
+}
+
+impl std::fmt::Display for DebugInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}", file, line),
+            (Some(file), None) => write!(f, "{}", file),
+            _ => write!(f, "?"),
+        }
+    }
+}
+
+/// Where to look for symbols that do not live in the image itself.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// PDB files to try for PE images; only one whose GUID and age match the
+    /// image's CodeView record is used, so both sides of a diff can share a list.
+    pub pdb: Vec<PathBuf>,
+    /// Directories searched for debug files: PDBs flat or in symbol-store
+    /// layout, ELF debug files by name or under `.build-id/`.
+    pub symbol_paths: Vec<PathBuf>,
+    /// Slice to load from a universal (fat) Mach-O binary (a thin one must
+    /// match it), or the instruction set of a raw image.
+    pub arch: Option<TargetArch>,
+    /// Treat the file as a flat image instead of parsing a container format.
+    pub raw: Option<raw::RawOptions>,
+}
+
+/// Name given to a function found without a symbol.
+pub fn synthetic_name(addr: u64) -> String {
+    format!("sub_{:x}", addr)
+}
+
+/// A loaded region of the image: an ELF `PT_LOAD` segment, a PE section or
+/// a Mach-O segment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub name: String,
+    pub vaddr: u64,
+    pub vsize: u64,
+    /// File offset and length of the bytes backing the start of the region;
+    /// anything past `file_size` (e.g. `.bss`) is zero-filled at runtime.
+    pub offset: u64,
+    pub file_size: u64,
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+    /// Archive member the region comes from.
+    #[serde(default)]
+    pub member: Option<String>,
+}
+
+impl Segment {
+    pub fn contains(&self, addr: u64) -> bool {
+        addr >= self.vaddr && addr - self.vaddr < self.vsize
+    }
+}
+
+/// Instruction set (or data) starting at a mapping symbol.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MappingKind {
+    Arm,
+    Thumb,
+    Data,
+}
+
+/// An ARM ELF mapping symbol (`$a`, `$t`, `$d`), or the equivalent implied by
+/// the Thumb bit of a function symbol. Kept sorted by address.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MappingSymbol {
+    pub addr: u64,
+    pub kind: MappingKind,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BinaryImage {
     pub path: String,
-    pub arch: String,
+    pub arch: Architecture,
     pub bits: u8,
+    pub endianness: Endianness,
+    #[serde(default)]
+    pub abi: Abi,
+    /// Preferred load address (PE `ImageBase`, lowest `PT_LOAD` for ELF,
+    /// lowest mapped segment for Mach-O).
+    pub image_base: u64,
+    pub segments: Vec<Segment>,
     pub functions: Vec<FunctionSymbol>,
-    pub data: Vec<u8>,
+    pub mapping_symbols: Vec<MappingSymbol>,
+    /// Kernel code rewritten at run time, sorted by address.
+    #[serde(default)]
+    pub patch_sites: Vec<kernel::PatchSite>,
+    /// Imported function names by PLT stub, GOT, IAT or `extern` slot address.
+    #[serde(default)]
+    pub imports: BTreeMap<u64, String>,
+    #[serde(default)]
+    pub metadata: metadata::Metadata,
+    #[serde(skip)]
+    pub data: ImageData,
+}
+
+#[derive(Clone)]
+enum Backing {
+    Mapped(Arc<Mmap>),
+    Owned(Arc<[u8]>),
+}
+
+/// The bytes of an image file: a shared view of its memory map, paged in as
+/// functions are read, or owned bytes when the loader had to patch them.
+/// Clones share the underlying file.
+#[derive(Clone)]
+pub struct ImageData {
+    backing: Backing,
+    range: Range<usize>,
+}
+
+impl ImageData {
+    pub fn map(path: &Path) -> Result<Self> {
+        let f = File::open(path)?;
+        // The file is only read; like any mmap, it must not be truncated
+        // while we hold it.
+        let map = unsafe { Mmap::map(&f)? };
+        Ok(Self { range: 0..map.len(), backing: Backing::Mapped(Arc::new(map)) })
+    }
+
+    /// A view of `range` of these bytes, sharing the same file.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        let start = (self.range.start + range.start).min(self.range.end);
+        let end = (self.range.start + range.end).clamp(start, self.range.end);
+        Self { backing: self.backing.clone(), range: start..end }
+    }
+}
+
+impl From<Vec<u8>> for ImageData {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self { range: 0..bytes.len(), backing: Backing::Owned(bytes.into()) }
+    }
+}
+
+impl Default for ImageData {
+    fn default() -> Self {
+        Vec::new().into()
+    }
+}
+
+impl Deref for ImageData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.backing {
+            Backing::Mapped(map) => &map[self.range.clone()],
+            Backing::Owned(bytes) => &bytes[self.range.clone()],
+        }
+    }
+}
+
+impl std::fmt::Debug for ImageData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.backing { Backing::Mapped(_) => "mapped", Backing::Owned(_) => "owned" };
+        write!(f, "ImageData({} bytes, {})", self.range.len(), kind)
+    }
+}
+
+impl BinaryImage {
+    pub fn segment_at(&self, addr: u64) -> Option<&Segment> {
+        self.segments.iter().find(|s| s.contains(addr))
+    }
+
+    /// Translate a virtual address into a file offset, if it is file-backed.
+    pub fn va_to_offset(&self, addr: u64) -> Option<usize> {
+        let seg = self.segment_at(addr)?;
+        let delta = addr - seg.vaddr;
+        if delta >= seg.file_size { return None; }
+        usize::try_from(seg.offset + delta).ok().filter(|&off| off < self.data.len())
+    }
+
+    /// Read up to `len` bytes at virtual address `addr`.
+    ///
+    /// The returned slice is truncated at the end of the containing segment's
+    /// file-backed bytes, so it may be shorter than `len`; `None` means `addr`
+    /// is not mapped from the file at all.
+    pub fn read_va(&self, addr: u64, len: usize) -> Option<&[u8]> {
+        let seg = self.segment_at(addr)?;
+        let off = self.va_to_offset(addr)?;
+        let seg_end = (seg.offset + seg.file_size) as usize;
+        let end = off.saturating_add(len).min(seg_end).min(self.data.len());
+        Some(&self.data[off..end])
+    }
+
+    pub fn is_executable(&self, addr: u64) -> bool {
+        self.segment_at(addr).is_some_and(|s| s.exec)
+    }
+
+    /// The runtime-patched site starting at `addr`, if any.
+    pub fn patch_site(&self, addr: u64) -> Option<&kernel::PatchSite> {
+        self.patch_sites.binary_search_by_key(&addr, |s| s.addr).ok().map(|i| &self.patch_sites[i])
+    }
+}
+
+fn elf_segments(elf: &elf::Elf) -> Vec<Segment> {
+    use elf::program_header::{PT_LOAD, PF_R, PF_W, PF_X};
+    let mut segs: Vec<Segment> = elf.program_headers.iter()
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .enumerate()
+        .map(|(i, ph)| Segment {
+            name: format!("LOAD{}", i),
+            vaddr: ph.p_vaddr,
+            vsize: ph.p_memsz,
+            offset: ph.p_offset,
+            file_size: ph.p_filesz.min(ph.p_memsz),
+            read: ph.p_flags & PF_R != 0,
+            write: ph.p_flags & PF_W != 0,
+            exec: ph.p_flags & PF_X != 0,
+            member: None,
+        })
+        .collect();
+    if segs.is_empty() {
+        // No program headers: fall back to allocated sections.
+        use elf::section_header::{SHF_ALLOC, SHF_WRITE, SHF_EXECINSTR, SHT_NOBITS};
+        for sh in &elf.section_headers {
+            if sh.sh_flags & SHF_ALLOC as u64 == 0 || sh.sh_size == 0 { continue; }
+            segs.push(Segment {
+                name: elf.shdr_strtab.get_at(sh.sh_name).unwrap_or("").to_string(),
+                vaddr: sh.sh_addr,
+                vsize: sh.sh_size,
+                offset: sh.sh_offset,
+                file_size: if sh.sh_type == SHT_NOBITS { 0 } else { sh.sh_size },
+                read: true,
+                write: sh.sh_flags & SHF_WRITE as u64 != 0,
+                exec: sh.sh_flags & SHF_EXECINSTR as u64 != 0,
+                member: None,
+            });
+        }
+    }
+    segs
+}
+
+fn pe_segments(pe: &pe::PE) -> Vec<Segment> {
+    use pe::section_table::{IMAGE_SCN_MEM_READ, IMAGE_SCN_MEM_WRITE, IMAGE_SCN_MEM_EXECUTE};
+    let base = pe.image_base as u64;
+    pe.sections.iter().map(|s| {
+        let name = s.name().map(str::to_string).unwrap_or_default();
+        // Raw data is rounded up to FileAlignment; never claim more than the section maps.
+        let vsize = if s.virtual_size != 0 { s.virtual_size } else { s.size_of_raw_data } as u64;
+        Segment {
+            name,
+            vaddr: base + s.virtual_address as u64,
+            vsize,
+            offset: s.pointer_to_raw_data as u64,
+            file_size: (s.size_of_raw_data as u64).min(vsize),
+            read: s.characteristics & IMAGE_SCN_MEM_READ != 0,
+            write: s.characteristics & IMAGE_SCN_MEM_WRITE != 0,
+            exec: s.characteristics & IMAGE_SCN_MEM_EXECUTE != 0,
+            member: None,
+        }
+    }).collect()
+}
+
+fn elf_section<'a>(elf: &'a elf::Elf, name: &str) -> Option<&'a elf::SectionHeader> {
+    elf.section_headers.iter().find(|sh| elf.shdr_strtab.get_at(sh.sh_name) == Some(name))
+}
+
+fn elf_section_data<'a>(data: &'a [u8], sh: &elf::SectionHeader) -> Option<&'a [u8]> {
+    if sh.sh_type == elf::section_header::SHT_NOBITS { return None; }
+    data.get(sh.sh_offset as usize..sh.sh_offset.checked_add(sh.sh_size)? as usize)
+}
+
+/// Code pointers an ELF image hands to the dynamic loader or C runtime: the
+/// entry point, `DT_INIT`/`DT_FINI` and the `.init_array`/`.fini_array` slots.
+/// Thumb bits are left in place.
+fn elf_code_pointers(elf: &elf::Elf, data: &[u8]) -> Vec<u64> {
+    let mut out = vec![elf.entry];
+    if let Some(dynamic) = &elf.dynamic {
+        out.extend([dynamic.info.init, dynamic.info.fini]);
+    }
+    // PIE files may leave the array slots zero and fill them in through
+    // RELATIVE relocations, whose addend is the target.
+    let relative: HashMap<u64, u64> = elf.dynrelas.iter()
+        .filter(|r| r.r_sym == 0)
+        .map(|r| (r.r_offset, r.r_addend.unwrap_or(0) as u64))
+        .collect();
+    let word = if elf.is_64 { 8 } else { 4 };
+    for name in [".preinit_array", ".init_array", ".fini_array"] {
+        let Some(sh) = elf_section(elf, name) else { continue };
+        let Some(bytes) = elf_section_data(data, sh) else { continue };
+        for (i, slot) in bytes.chunks_exact(word).enumerate() {
+            let value = match (word, elf.little_endian) {
+                (8, true) => u64::from_le_bytes(slot.try_into().unwrap()),
+                (8, false) => u64::from_be_bytes(slot.try_into().unwrap()),
+                (_, true) => u32::from_le_bytes(slot.try_into().unwrap()) as u64,
+                (_, false) => u32::from_be_bytes(slot.try_into().unwrap()) as u64,
+            };
+            let value = if value == 0 { relative.get(&(sh.sh_addr + (i * word) as u64)).copied().unwrap_or(0) } else { value };
+            // Old-style `.ctors` lists are terminated by -1.
+            if value != u64::MAX >> (64 - word * 8) { out.push(value); }
+        }
+    }
+    out.retain(|&a| a != 0);
+    out
 }
 
-fn map_file(path: &Path) -> Result<Vec<u8>> {
-    let f = File::open(path)?;
-    let m = unsafe { Mmap::map(&f)? };
-    Ok(m.as_ref().to_vec())
+/// `[pc_begin, pc_end)` of every FDE in `.eh_frame`. Without section headers
+/// the section is found through `PT_GNU_EH_FRAME` (`.eh_frame_hdr`).
+fn elf_fde_ranges(elf: &elf::Elf, data: &[u8]) -> Vec<(u64, u64)> {
+    let address_size = if elf.is_64 { 8 } else { 4 };
+    let (addr, bytes) = match elf_section(elf, ".eh_frame") {
+        Some(sh) => match elf_section_data(data, sh) {
+            Some(bytes) => (sh.sh_addr, bytes),
+            None => return Vec::new(),
+        },
+        None => {
+            let Some(hdr) = elf.program_headers.iter().find(|ph| ph.p_type == elf::program_header::PT_GNU_EH_FRAME) else { return Vec::new() };
+            let Some(hdr_bytes) = data.get(hdr.p_offset as usize..(hdr.p_offset + hdr.p_filesz) as usize) else { return Vec::new() };
+            let Some(addr) = eh_frame::eh_frame_from_hdr(hdr_bytes, hdr.p_vaddr, address_size, !elf.little_endian) else { return Vec::new() };
+            // The section runs to its zero terminator; hand over the rest of the segment.
+            let Some(bytes) = elf.program_headers.iter()
+                .find(|ph| ph.p_type == elf::program_header::PT_LOAD && addr >= ph.p_vaddr && addr - ph.p_vaddr < ph.p_filesz)
+                .and_then(|ph| data.get((ph.p_offset + addr - ph.p_vaddr) as usize..(ph.p_offset + ph.p_filesz) as usize))
+            else { return Vec::new() };
+            (addr, bytes)
+        }
+    };
+    let bases = eh_frame::EhFrameBases {
+        eh_frame: addr,
+        text: elf_section(elf, ".text").map(|s| s.sh_addr),
+        got: elf_section(elf, ".got").map(|s| s.sh_addr),
+    };
+    eh_frame::fde_ranges(bytes, bases, address_size, !elf.little_endian)
 }
 
-fn collect_elf(elf: &elf::Elf, bytes: &[u8]) -> Vec<FunctionSymbol> {
+/// Defined `STT_FUNC` symbols from `.symtab` and `.dynsym`.
+fn elf_function_symbols(elf: &elf::Elf, addr_mask: u64) -> Vec<FunctionSymbol> {
     let mut funs = Vec::new();
-    for sym in elf.syms.iter() {
-        let is_func = sym.st_type() == elf::sym::STT_FUNC;
-        if !is_func || sym.st_size == 0 { continue; }
-        if let Some(Ok(name)) = elf.strtab.get(sym.st_name) {
+    let mut named = HashSet::new();
+    // `.dynsym` repeats the exported part of `.symtab` but survives stripping.
+    for (syms, strtab) in [(&elf.syms, &elf.strtab), (&elf.dynsyms, &elf.dynstrtab)] {
+        for sym in syms.iter() {
+            let is_func = sym.st_type() == elf::sym::STT_FUNC;
+            if !is_func || sym.st_shndx == elf::section_header::SHN_UNDEF as usize { continue; }
+            let Some(name) = strtab.get_at(sym.st_name) else { continue };
+            let start = sym.st_value & addr_mask;
+            if !named.insert((start, name)) { continue; }
             funs.push(FunctionSymbol {
                 name: name.to_string(),
-                start: sym.st_value,
+                start,
+                // zero when unknown; the disassembler bounds it
                 size: sym.st_size,
+                fragments: Vec::new(),
+                debug: None,
+                demangled: None,
             });
         }
     }
     funs
 }
 
-fn collect_pe(pe: &pe::PE, _bytes: &[u8]) -> Vec<FunctionSymbol> {
+/// `debug` is the separate debug file, if one was found; DWARF is read from
+/// it rather than from the image.
+fn collect_elf(elf: &elf::Elf, data: &[u8], arch: Architecture, segments: &[Segment], code_pointers: &[u64], debug: Option<(&elf::Elf, &[u8])>) -> Vec<FunctionSymbol> {
+    // Bit 0 of an ARM function symbol selects Thumb; it is not part of the address.
+    let addr_mask = if arch == Architecture::Arm { !1 } else { !0 };
+    let mut funs = elf_function_symbols(elf, addr_mask);
+
+    // Debug files keep the full `.symtab` of a stripped image.
+    if let Some((debug_elf, _)) = debug {
+        merge_symbols(&mut funs, elf_function_symbols(debug_elf, addr_mask));
+    }
+    let (dwarf_elf, dwarf_data) = debug.unwrap_or((elf, data));
+    merge_symbols(&mut funs, dwarf::functions(dwarf_elf, dwarf_data, addr_mask));
+    // Stripped Go binaries still name every function in their pclntab.
+    merge_symbols(&mut funs, golang::elf(elf, data));
+
+    // FDE ranges come from the compiler and beat missing or hand-written sizes.
+    let fdes = elf_fde_ranges(elf, data);
+    let fde_end: HashMap<u64, u64> = fdes.iter().copied().collect();
+    for f in &mut funs {
+        if let Some(&end) = fde_end.get(&f.start) {
+            f.size = end.saturating_sub(f.start);
+        }
+    }
+    merge_cold_fragments(&mut funs);
+
+    // Seeds for stripped images.
+    let mut known: HashSet<u64> = funs.iter()
+        .flat_map(|f| std::iter::once(f.start).chain(f.fragments.iter().map(|r| r.0)))
+        .collect();
+    let seeds = code_pointers.iter().map(|&a| (a & addr_mask, 0))
+        .chain(fdes.into_iter().map(|(start, end)| (start, end.saturating_sub(start))));
+    for (start, size) in seeds {
+        let executable = segments.iter().any(|s| s.exec && s.contains(start));
+        if !executable || !known.insert(start) { continue; }
+        funs.push(FunctionSymbol { name: synthetic_name(start), start, size, fragments: Vec::new(), debug: None, demangled: None });
+    }
+    funs
+}
+
+/// `foo` for a GCC cold part named `foo.cold` or `foo.cold.N`.
+fn cold_parent(name: &str) -> Option<&str> {
+    let parent = name.strip_suffix(".cold").or_else(|| {
+        let (parent, n) = name.rsplit_once(".cold.")?;
+        (!n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())).then_some(parent)
+    })?;
+    (!parent.is_empty()).then_some(parent)
+}
+
+/// Fold `foo.cold` / `foo.cold.N` parts into `foo` as fragments. Parts
+/// without a known size or a single parent of that name (two static `foo`s)
+/// stay functions of their own.
+fn merge_cold_fragments(funs: &mut Vec<FunctionSymbol>) {
+    // None once a name turns up at two addresses.
+    let mut index: HashMap<&str, Option<usize>> = HashMap::new();
+    for (i, f) in funs.iter().enumerate().filter(|(_, f)| cold_parent(&f.name).is_none()) {
+        index.entry(f.name.as_str())
+            .and_modify(|p| if p.is_some_and(|p| funs[p].start != f.start) { *p = None })
+            .or_insert(Some(i));
+    }
+    let index: HashMap<String, usize> = index.into_iter().filter_map(|(name, p)| Some((name.to_string(), p?))).collect();
+    let mut merged = vec![false; funs.len()];
+    for i in 0..funs.len() {
+        let Some(parent) = cold_parent(&funs[i].name) else { continue };
+        let Some(&p) = index.get(parent) else { continue };
+        if funs[i].size == 0 { continue; }
+        let part = (funs[i].start, funs[i].start + funs[i].size);
+        if !funs[p].fragments.contains(&part) { funs[p].fragments.push(part); }
+        merged[i] = true;
+    }
+    let mut i = 0;
+    funs.retain(|_| { i += 1; !merged[i - 1] });
+}
+
+fn collect_mapping_symbols(elf: &elf::Elf, arch: Architecture, code_pointers: &[u64]) -> Vec<MappingSymbol> {
+    if arch != Architecture::Arm { return Vec::new(); }
+    let implied_by = |addr: u64| {
+        let kind = if addr & 1 != 0 { MappingKind::Thumb } else { MappingKind::Arm };
+        MappingSymbol { addr: addr & !1, kind }
+    };
+    let mut explicit = Vec::new();
+    let mut implied: Vec<MappingSymbol> = code_pointers.iter().map(|&a| implied_by(a)).collect();
+    for sym in elf.dynsyms.iter().filter(|s| s.st_type() == elf::sym::STT_FUNC && s.st_value != 0) {
+        implied.push(implied_by(sym.st_value));
+    }
+    for sym in elf.syms.iter() {
+        if sym.st_type() == elf::sym::STT_FUNC {
+            implied.push(implied_by(sym.st_value));
+            continue;
+        }
+        let Some(name) = elf.strtab.get_at(sym.st_name) else { continue };
+        // `$t` or `$t.<anything>`
+        let kind = match name.split('.').next() {
+            Some("$a") => MappingKind::Arm,
+            Some("$t") => MappingKind::Thumb,
+            Some("$d") => MappingKind::Data,
+            _ => continue,
+        };
+        explicit.push(MappingSymbol { addr: sym.st_value, kind });
+    }
+    // Explicit mapping symbols win over what a function symbol implies.
+    explicit.extend(implied);
+    explicit.sort_by_key(|m| m.addr);
+    explicit.dedup_by_key(|m| m.addr);
+    explicit
+}
+
+fn collect_pe(pe: &pe::PE, data: &[u8], segments: &[Segment]) -> Vec<FunctionSymbol> {
     // For PE, rely on export table and symbols if present. Fallbacks possible later.
+    let base = pe.image_base as u64;
+    // Exports and `.pdata` of Thumb code have bit 0 set, as ELF symbols do.
+    let addr_mask = if is_windows_on_arm(pe) { !1 } else { !0 };
     let mut funs = Vec::new();
-    if let Some(exports) = &pe.exports {
-        for e in exports {
-            let name = e.name.unwrap_or_else(|| format!("ord_{}", e.rva));
-            funs.push(FunctionSymbol {
-                name,
-                start: e.rva as u64,
-                size: 0, // unknown; will rely on disassembler to bound
-            });
+    for e in &pe.exports {
+        // Forwarders point at a string in the export directory, not at code.
+        if e.reexport.is_some() { continue; }
+        let name = e.name.map(str::to_string).unwrap_or_else(|| format!("ord_{}", e.rva));
+        funs.push(FunctionSymbol {
+            name,
+            start: (base + e.rva as u64) & addr_mask,
+            size: 0, // unknown; will rely on disassembler to bound
+            fragments: Vec::new(),
+            debug: None,
+            demangled: None,
+        });
+    }
+
+    // Every non-leaf x64 function has a `.pdata` entry with exact bounds.
+    let mut known: HashMap<u64, usize> = funs.iter().enumerate().map(|(i, f)| (f.start, i)).collect();
+    let pdata = if is_windows_on_arm(pe) { arm_pdata_functions(pe, data, segments) } else { pdata_functions(pe, data, segments) };
+    for (start, (end, fragments)) in pdata {
+        let (start, end) = ((base + start as u64) & addr_mask, base + end as u64);
+        let fragments = fragments.into_iter().map(|(s, e)| (base + s as u64, base + e as u64)).collect();
+        match known.get(&start) {
+            Some(&i) => {
+                funs[i].size = end - start;
+                funs[i].fragments = fragments;
+            }
+            None => {
+                known.insert(start, funs.len());
+                funs.push(FunctionSymbol { name: synthetic_name(start), start, size: end - start, fragments, debug: None, demangled: None });
+            }
         }
     }
     funs
 }
 
+/// Windows on ARM: Thumb-2 only, with no mapping symbols to say so.
+fn is_windows_on_arm(pe: &pe::PE) -> bool {
+    matches!(pe.header.coff_header.machine, pe::header::COFF_MACHINE_ARMNT | pe::header::COFF_MACHINE_THUMB)
+}
+
+/// Thumb state from the start of every executable section of a Windows on
+/// ARM image; without it the decoder would assume A32.
+fn pe_mapping_symbols(pe: &pe::PE, segments: &[Segment]) -> Vec<MappingSymbol> {
+    if !is_windows_on_arm(pe) { return Vec::new(); }
+    let mut out: Vec<_> = segments.iter()
+        .filter(|s| s.exec)
+        .map(|s| MappingSymbol { addr: s.vaddr, kind: MappingKind::Thumb })
+        .collect();
+    out.sort_by_key(|m| m.addr);
+    out
+}
+
+/// ARMNT `.pdata`, which goblin leaves unparsed: `begin -> (end, [])` by
+/// RVA, bit 0 (Thumb) still set on `begin`. Each 8-byte entry holds the
+/// begin address and either packed unwind data (low bits non-zero) or the
+/// RVA of an `.xdata` record; both give the length in halfwords.
+fn arm_pdata_functions(pe: &pe::PE, data: &[u8], segments: &[Segment]) -> BTreeMap<u32, (u32, Vec<(u32, u32)>)> {
+    let mut out = BTreeMap::new();
+    let Some(dir) = pe.header.optional_header.as_ref().and_then(|oh| oh.data_directories.get_exception_table()) else { return out };
+    let word = |rva: u32| -> Option<u32> {
+        let va = pe.image_base as u64 + rva as u64;
+        let seg = segments.iter().find(|s| s.contains(va))?;
+        let off = (seg.offset + va - seg.vaddr) as usize;
+        Some(u32::from_le_bytes(data.get(off..off + 4)?.try_into().ok()?))
+    };
+    for entry in (dir.virtual_address..dir.virtual_address.saturating_add(dir.size)).step_by(8) {
+        let (Some(begin), Some(unwind)) = (word(entry), word(entry + 4)) else { break };
+        let halfwords = if unwind & 3 != 0 {
+            (unwind >> 2) & 0x7ff
+        } else {
+            match word(unwind) { Some(xdata) => xdata & 0x3ffff, None => continue }
+        };
+        if begin == 0 || halfwords == 0 { continue; }
+        out.insert(begin, ((begin & !1) + halfwords * 2, Vec::new()));
+    }
+    out
+}
+
+/// `.pdata` entries grouped by the function they unwind as: primary
+/// `begin -> (end, chained parts)`, all RVAs. Parts whose unwind info chains
+/// to another entry (split or shrink-wrapped code) are fragments of the
+/// entry at the end of the chain.
+fn pdata_functions(pe: &pe::PE, data: &[u8], segments: &[Segment]) -> BTreeMap<u32, (u32, Vec<(u32, u32)>)> {
+    use pe::exception::RuntimeFunction;
+    // Bound on chain length, in case of cycles.
+    const MAX_CHAIN: usize = 32;
+    let mut out = BTreeMap::new();
+    let Some(exceptions) = &pe.exception_data else { return out };
+    // An odd unwind info address is the RVA of the parent entry itself.
+    let read_entry = |rva: u32| -> Option<RuntimeFunction> {
+        let va = pe.image_base as u64 + rva as u64;
+        let seg = segments.iter().find(|s| s.contains(va))?;
+        let off = (seg.offset + va - seg.vaddr) as usize;
+        let word = |i: usize| Some(u32::from_le_bytes(data.get(off + i * 4..off + i * 4 + 4)?.try_into().ok()?));
+        Some(RuntimeFunction { begin_address: word(0)?, end_address: word(1)?, unwind_info_address: word(2)? })
+    };
+    let parent = |f: RuntimeFunction| -> Option<RuntimeFunction> {
+        if f.unwind_info_address & 1 != 0 {
+            read_entry(f.unwind_info_address & !1)
+        } else {
+            exceptions.get_unwind_info(f, &pe.sections).ok()?.chained_info
+        }
+    };
+
+    let mut parts = Vec::new();
+    for f in exceptions.functions().filter_map(|f| f.ok()) {
+        if f.end_address <= f.begin_address { continue; }
+        let mut root = f;
+        for _ in 0..MAX_CHAIN {
+            match parent(root) {
+                Some(p) => root = p,
+                None => break,
+            }
+        }
+        if root.begin_address == f.begin_address {
+            out.entry(f.begin_address).or_insert((f.end_address, Vec::new())).0 = f.end_address;
+        } else {
+            parts.push((root.begin_address, root.end_address, (f.begin_address, f.end_address)));
+        }
+    }
+    for (begin, end, part) in parts {
+        out.entry(begin).or_insert((end, Vec::new())).1.push(part);
+    }
+    out
+}
+
+/// Fold functions from another source (a debug file, DWARF, a PDB) into
+/// `funs`: they name synthetic entries and fill in missing sizes, fragments
+/// and debug info; functions only the other source knows about are added.
+/// Earlier entries of `extra` win over later ones at the same address.
+fn merge_symbols(funs: &mut Vec<FunctionSymbol>, extra: Vec<FunctionSymbol>) {
+    let mut known: HashMap<u64, usize> = funs.iter().enumerate().map(|(i, f)| (f.start, i)).collect();
+    for e in extra {
+        match known.get(&e.start) {
+            Some(&i) => {
+                let f = &mut funs[i];
+                if f.name == synthetic_name(f.start) { f.name = e.name; }
+                if f.size == 0 { f.size = e.size; }
+                if f.fragments.is_empty() { f.fragments = e.fragments; }
+                if f.debug.is_none() { f.debug = e.debug; }
+            }
+            None => {
+                known.insert(e.start, funs.len());
+                funs.push(e);
+            }
+        }
+    }
+}
+
 pub fn load(path: &Path) -> Result<BinaryImage> {
-    let data = map_file(path)?;
+    load_with(path, &LoadOptions::default())
+}
+
+pub fn load_with(path: &Path, opts: &LoadOptions) -> Result<BinaryImage> {
+    let mut bin = load_image(path, opts)?;
+    demangle::annotate(&mut bin.functions);
+    Ok(bin)
+}
+
+fn load_image(path: &Path, opts: &LoadOptions) -> Result<BinaryImage> {
+    let data = ImageData::map(path)?;
+    if let Some(raw) = &opts.raw {
+        let Some(arch) = opts.arch.map(|t| t.arch) else { bail!("Raw images need an architecture (--arch)") };
+        return raw::load(path, data, arch, raw);
+    }
+    if object::is_relocatable(&data) {
+        // Relocations are applied in place, so objects get a private copy.
+        return object::load(path, data.to_vec());
+    }
     match Object::parse(&data)? {
         Object::Elf(elf) => {
-            let funs = collect_elf(&elf, &data);
-            Ok(BinaryImage {
+            let arch = Architecture::from_elf_machine(elf.header.e_machine, elf.is_64);
+            let segments = elf_segments(&elf);
+            let code_pointers = elf_code_pointers(&elf, &data);
+            let debug_data = debug_file::find(path, &elf, &data, opts).and_then(|p| ImageData::map(&p).ok());
+            let debug_elf = debug_data.as_deref().and_then(|d| elf::Elf::parse(d).ok().map(|e| (e, d)));
+            let debug = debug_elf.as_ref().map(|(e, d)| (e, *d));
+            let funs = collect_elf(&elf, &data, arch, &segments, &code_pointers, debug);
+            let mapping_symbols = collect_mapping_symbols(&elf, arch, &code_pointers);
+            let image_base = segments.iter().map(|s| s.vaddr).min().unwrap_or(0);
+            let patch_tables = kernel::elf_tables(&elf);
+            let imports = imports::elf(&elf, &data);
+            let metadata = metadata::elf(&elf, &data);
+            let mut bin = BinaryImage {
                 path: path.display().to_string(),
-                arch: "x86".to_string(),
+                arch,
                 bits: if elf.is_64 { 64 } else { 32 },
+                endianness: if elf.little_endian { Endianness::Little } else { Endianness::Big },
+                abi: Abi::from_elf(arch, elf.is_64, elf.header.e_flags),
+                image_base,
+                segments,
                 functions: funs,
+                mapping_symbols,
+                patch_sites: Vec::new(),
+                imports,
+                metadata,
                 data,
-            })
+            };
+            bin.patch_sites = kernel::patch_sites(&bin, &patch_tables);
+            Ok(bin)
         },
         Object::PE(pe) => {
-            let funs = collect_pe(&pe, &data);
-            // Assume 64-bit if optional header says so
-            let bits = if let Some(opt) = pe.header.optional_header {
-                if opt.standard_fields.magic == 0x20b { 64 } else { 32 }
-            } else { 64 };
+            let segments = pe_segments(&pe);
+            let mut funs = collect_pe(&pe, &data, &segments);
+            merge_symbols(&mut funs, golang::pe(&pe, &data));
+            let imports = imports::pe(&pe, &data, &segments);
+            let metadata = metadata::pe(&pe, &data, &segments);
+            let mapping_symbols = pe_mapping_symbols(&pe, &segments);
+            if let Some(pdb_path) = pdb::PdbId::from_pe(&pe).and_then(|id| pdb::find(path, &id, opts)) {
+                let base = pe.image_base as u64;
+                // Procedures come first; a later public symbol only fills gaps.
+                let pdb_funs = pdb::functions(&pdb_path)?.into_iter().map(|pf| FunctionSymbol {
+                    name: pf.name,
+                    start: base + pf.rva as u64,
+                    size: pf.size as u64,
+                    fragments: Vec::new(),
+                    debug: None,
+                    demangled: None,
+                }).collect();
+                merge_symbols(&mut funs, pdb_funs);
+            }
             Ok(BinaryImage {
                 path: path.display().to_string(),
-                arch: "x86".to_string(),
-                bits,
+                arch: Architecture::from_coff_machine(pe.header.coff_header.machine),
+                bits: if pe.is_64 { 64 } else { 32 },
+                endianness: Endianness::Little,
+                abi: Abi::Windows,
+                image_base: pe.image_base as u64,
+                segments,
                 functions: funs,
+                mapping_symbols,
+                patch_sites: Vec::new(),
+                imports,
+                metadata,
                 data,
             })
         },
+        Object::Mach(mach) => {
+            let (macho, range) = match mach {
+                goblin::mach::Mach::Binary(macho) => {
+                    macho::check_thin(&macho, opts.arch)?;
+                    (macho, 0..data.len())
+                },
+                goblin::mach::Mach::Fat(fat) => macho::select_slice(&fat, opts.arch)?,
+            };
+            // Segment offsets are relative to the slice.
+            let slice = data.slice(range);
+            let arch = Architecture::from_macho_cputype(macho.header.cputype);
+            let segments = macho::segments(&macho);
+            let image_base = segments.iter().map(|s| s.vaddr).min().unwrap_or(0);
+            let mut funs = macho::functions(&macho, &slice, arch);
+            merge_symbols(&mut funs, golang::macho(&macho, &slice));
+            Ok(BinaryImage {
+                path: path.display().to_string(),
+                arch,
+                bits: if macho.is_64 { 64 } else { 32 },
+                endianness: if macho.little_endian { Endianness::Little } else { Endianness::Big },
+                abi: Abi::Darwin,
+                image_base,
+                functions: funs,
+                mapping_symbols: macho::mapping_symbols(&macho, &slice, arch),
+                segments,
+                patch_sites: Vec::new(),
+                imports: BTreeMap::new(),
+                metadata: metadata::macho(&macho),
+                data: slice,
+            })
+        },
         other => bail!("Unsupported object format: {:?}", other),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fun(name: &str, start: u64, size: u64) -> FunctionSymbol {
+        FunctionSymbol { name: name.to_string(), start, size, fragments: Vec::new(), debug: None, demangled: None }
+    }
+
+    #[test]
+    fn cold_parts_need_an_exact_suffix() {
+        assert_eq!(cold_parent("foo.cold"), Some("foo"));
+        assert_eq!(cold_parent("foo.part.0.cold.12"), Some("foo.part.0"));
+        assert_eq!(cold_parent("foo.colder"), None);
+        assert_eq!(cold_parent("foo.cold.x"), None);
+        assert_eq!(cold_parent("foo.cold."), None);
+        assert_eq!(cold_parent(".cold"), None);
+    }
+
+    #[test]
+    fn cold_parts_merge_into_a_unique_parent() {
+        let mut funs = vec![
+            fun("main", 0x1000, 0x40), fun("main.cold", 0x2000, 0x10),
+            fun("helper", 0x1100, 0x20), fun("helper", 0x1200, 0x20), fun("helper.cold.1", 0x2100, 0x10),
+            fun("init.coldstart", 0x1300, 0x10),
+        ];
+        merge_cold_fragments(&mut funs);
+        let names: Vec<_> = funs.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["main", "helper", "helper", "helper.cold.1", "init.coldstart"]);
+        assert_eq!(funs[0].fragments, [(0x2000, 0x2010)]);
+        assert!(funs[1].fragments.is_empty() && funs[2].fragments.is_empty());
+    }
+}
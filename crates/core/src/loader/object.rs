@@ -0,0 +1,754 @@
+//! Relocatable objects (ELF `ET_REL`, including Linux `.ko` modules, COFF
+//! `.obj`) and `ar` archives of them.
+//!
+//! Object sections have no addresses yet, so they are laid out one after
+//! another in a synthetic address space. Symbols defined by any member
+//! resolve across the whole archive and undefined ones get a slot in an
+//! `extern` region; the common call and pointer relocations are then applied,
+//! so a reference to the same symbol reads the same in both builds.
+
+use super::kernel::{self, Table};
+use super::metadata::{self, Metadata, Section};
+use super::{BinaryImage, FunctionSymbol, MappingKind, MappingSymbol, Segment};
+use crate::arch::{Abi, Architecture, Endianness};
+use anyhow::{Result, bail};
+use goblin::{Hint, Object, elf, pe};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// First synthetic address; keeps unresolved (zero) references out of range.
+const LAYOUT_BASE: u64 = 0x10000;
+/// Spacing of `extern` slots.
+const EXTERN_SLOT: u64 = 16;
+
+// Not in goblin's table.
+const IMAGE_REL_ARM64_BRANCH26: u16 = 0x0003;
+const IMAGE_REL_ARM64_ADDR64: u16 = 0x000E;
+
+/// Whether `data` is an archive or a relocatable object rather than a
+/// linked image.
+pub fn is_relocatable(data: &[u8]) -> bool {
+    let Some(magic) = data.get(..16).and_then(|b| <&[u8; 16]>::try_from(b).ok()) else { return false };
+    match goblin::peek_bytes(magic) {
+        Ok(Hint::Archive | Hint::COFF) => true,
+        Ok(Hint::Elf(_)) => elf::Elf::parse_header(data).is_ok_and(|h| h.e_type == elf::header::ET_REL),
+        _ => false,
+    }
+}
+
+/// How a relocation is written back, after resolving `S + A`.
+#[derive(Clone, Copy)]
+enum Patch {
+    Abs64,
+    Abs32,
+    /// 32-bit `S + A - P`, e.g. x86 `call rel32`.
+    Rel32,
+    /// AArch64 `B`/`BL` imm26, in words from `P`.
+    Branch26,
+    /// A32 `B`/`BL` imm24, in words from `P`; a `BL` to Thumb code becomes
+    /// `BLX`.
+    ArmBranch24,
+    /// Thumb-2 `BL`/`BLX`/`B.W`, in halfwords from `P`; calls become `BL` or
+    /// `BLX` to match the instruction set of the target.
+    ThumbBranch,
+    /// RISC-V `auipc` + `jalr` pair, `S + A - P` split across both.
+    RiscvCall,
+}
+
+/// Offset of a Thumb-2 `BL`/`BLX`/`B.W`: `S:I1:I2:imm10:imm11:0`.
+fn thumb_branch_offset(insn: u32) -> i32 {
+    let (hw1, hw2) = (insn & 0xffff, insn >> 16);
+    let s = (hw1 >> 10) & 1;
+    let (i1, i2) = (!((hw2 >> 13) ^ s) & 1, !((hw2 >> 11) ^ s) & 1);
+    let imm = s << 24 | i1 << 23 | i2 << 22 | (hw1 & 0x3ff) << 12 | (hw2 & 0x7ff) << 1;
+    ((imm << 7) as i32) >> 7
+}
+
+impl Patch {
+    fn width(self) -> usize {
+        match self {
+            Patch::Abs64 | Patch::RiscvCall => 8,
+            _ => 4,
+        }
+    }
+
+    /// The addend stored in place, for REL-style (and COFF) relocations.
+    fn implicit_addend(self, bytes: &[u8], little: bool) -> i64 {
+        let word = |n: usize| {
+            let mut v = 0u64;
+            for i in 0..n {
+                let b = bytes[if little { n - 1 - i } else { i }];
+                v = (v << 8) | b as u64;
+            }
+            v
+        };
+        match self {
+            Patch::Abs64 => word(8) as i64,
+            Patch::Abs32 | Patch::Rel32 => word(4) as u32 as i32 as i64,
+            Patch::Branch26 => (((word(4) as u32) << 6) as i32 >> 4) as i64,
+            Patch::ArmBranch24 => (((word(4) as u32) << 8) as i32 >> 6) as i64,
+            Patch::ThumbBranch => thumb_branch_offset(word(4) as u32) as i64,
+            Patch::RiscvCall => {
+                let (hi, lo) = (word(4) as u32, (word(8) >> 32) as u32);
+                (hi & 0xffff_f000) as i32 as i64 + (lo as i32 >> 20) as i64
+            }
+        }
+    }
+
+    fn write(self, bytes: &mut [u8], value: u64, place: u64, little: bool) {
+        let put = |bytes: &mut [u8], v: u64, n: usize| {
+            for i in 0..n {
+                bytes[if little { i } else { n - 1 - i }] = (v >> (8 * i)) as u8;
+            }
+        };
+        match self {
+            Patch::Abs64 => put(bytes, value, 8),
+            Patch::Abs32 => put(bytes, value, 4),
+            Patch::Rel32 => put(bytes, value.wrapping_sub(place), 4),
+            Patch::Branch26 => {
+                let insn = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+                let imm = (value.wrapping_sub(place) >> 2) as u32 & 0x03ff_ffff;
+                bytes[..4].copy_from_slice(&((insn & !0x03ff_ffff) | imm).to_le_bytes());
+            }
+            Patch::ArmBranch24 => {
+                let mut insn = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+                let off = (value & !1).wrapping_sub(place) as u32;
+                if value & 1 != 0 && (insn >> 24) & 0xf == 0xb {
+                    // BLX takes bit 1 of the offset in its H bit.
+                    insn = 0xfa00_0000 | (off & 2) << 23;
+                }
+                insn = (insn & !0x00ff_ffff) | ((off >> 2) & 0x00ff_ffff);
+                bytes[..4].copy_from_slice(&insn.to_le_bytes());
+            }
+            Patch::ThumbBranch => {
+                let insn = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+                let mut hw2 = insn >> 16;
+                let mut from = place;
+                if hw2 & 0x4000 != 0 {
+                    // A call: BL stays in Thumb, BLX switches to ARM and is
+                    // relative to the word-aligned `P`.
+                    if value & 1 != 0 { hw2 |= 0x1000 } else { hw2 &= !0x1000; from &= !3 }
+                }
+                let off = (value & !1).wrapping_sub(from) as u32;
+                let s = (off >> 24) & 1;
+                let (j1, j2) = (!(((off >> 23) & 1) ^ s) & 1, !(((off >> 22) & 1) ^ s) & 1);
+                let hw1 = 0xf000 | s << 10 | (off >> 12) & 0x3ff;
+                hw2 = (hw2 & 0xd000) | j1 << 13 | j2 << 11 | (off >> 1) & 0x7ff;
+                bytes[..4].copy_from_slice(&(hw1 | hw2 << 16).to_le_bytes());
+            }
+            Patch::RiscvCall => {
+                let off = value.wrapping_sub(place) as u32;
+                // `jalr` sign-extends its part, so round the `auipc` part up.
+                let hi = off.wrapping_add(0x800) & 0xffff_f000;
+                let auipc = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+                let jalr = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+                bytes[..4].copy_from_slice(&((auipc & 0xfff) | hi).to_le_bytes());
+                bytes[4..8].copy_from_slice(&((jalr & 0xf_ffff) | off.wrapping_sub(hi) << 20).to_le_bytes());
+            }
+        }
+    }
+}
+
+/// A relocation: patch `file` (offset into the image data, at address
+/// `place`) with the value of symbol `sym` of its member plus `addend`
+/// (`None` when it is stored in place) plus `bias`.
+struct Fixup {
+    file: usize,
+    place: u64,
+    sym: usize,
+    addend: Option<i64>,
+    bias: i64,
+    patch: Patch,
+}
+
+/// File offset of the `width` bytes at `offset` into a section of `size`
+/// bytes stored `start` bytes into a member of `len` bytes at `base`, unless
+/// any of them lie outside the section or the member.
+fn fixup_offset(base: usize, len: usize, start: u64, size: u64, offset: u64, width: usize) -> Option<usize> {
+    if offset.checked_add(width as u64)? > size { return None; }
+    let at = start.checked_add(offset)?;
+    if at.checked_add(width as u64)? > len as u64 { return None; }
+    base.checked_add(usize::try_from(at).ok()?)
+}
+
+/// Relocations understood for x86, x86-64, ARM, AArch64 and RISC-V objects;
+/// code of other machines is left as assembled.
+fn elf_patch(machine: u16, r_type: u32) -> Option<Patch> {
+    use elf::header::*;
+    use elf::reloc::*;
+    Some(match (machine, r_type) {
+        (EM_X86_64, R_X86_64_64) => Patch::Abs64,
+        (EM_X86_64, R_X86_64_32 | R_X86_64_32S) => Patch::Abs32,
+        // GOT-relative loads are resolved to the symbol itself.
+        (EM_X86_64, R_X86_64_PC32 | R_X86_64_PLT32 | R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX) => Patch::Rel32,
+        (EM_386, R_386_32) => Patch::Abs32,
+        (EM_386, R_386_PC32 | R_386_PLT32) => Patch::Rel32,
+        (EM_AARCH64, R_AARCH64_ABS64) => Patch::Abs64,
+        (EM_AARCH64, R_AARCH64_PREL32) => Patch::Rel32,
+        (EM_AARCH64, R_AARCH64_CALL26 | R_AARCH64_JUMP26) => Patch::Branch26,
+        (EM_ARM, R_ARM_ABS32) => Patch::Abs32,
+        (EM_ARM, R_ARM_CALL | R_ARM_JUMP24) => Patch::ArmBranch24,
+        // `R_ARM_THM_CALL` under its old name.
+        (EM_ARM, R_ARM_THM_PC22 | R_ARM_THM_JUMP24) => Patch::ThumbBranch,
+        (EM_RISCV, R_RISCV_64) => Patch::Abs64,
+        (EM_RISCV, R_RISCV_32) => Patch::Abs32,
+        (EM_RISCV, R_RISCV_CALL | R_RISCV_CALL_PLT) => Patch::RiscvCall,
+        _ => return None,
+    })
+}
+
+/// COFF relocations store their addend in place; the second value is the
+/// bias on top of it, as `REL32_n` is relative to `n` bytes past the end of
+/// the field.
+fn coff_patch(machine: u16, typ: u16) -> Option<(Patch, i64)> {
+    use pe::header::*;
+    use pe::relocation::*;
+    Some(match (machine, typ) {
+        (COFF_MACHINE_X86_64, IMAGE_REL_AMD64_ADDR64) => (Patch::Abs64, 0),
+        (COFF_MACHINE_X86_64, IMAGE_REL_AMD64_ADDR32) => (Patch::Abs32, 0),
+        (COFF_MACHINE_X86_64, IMAGE_REL_AMD64_REL32..=IMAGE_REL_AMD64_REL32_5) => (Patch::Rel32, -4 - (typ - IMAGE_REL_AMD64_REL32) as i64),
+        (COFF_MACHINE_X86, IMAGE_REL_I386_DIR32) => (Patch::Abs32, 0),
+        (COFF_MACHINE_X86, IMAGE_REL_I386_REL32) => (Patch::Rel32, -4),
+        (COFF_MACHINE_ARM64, IMAGE_REL_ARM64_ADDR64) => (Patch::Abs64, 0),
+        (COFF_MACHINE_ARM64, IMAGE_REL_ARM64_BRANCH26) => (Patch::Branch26, 0),
+        _ => return None,
+    })
+}
+
+/// One object after layout: symbol addresses by symbol-table index, or the
+/// name to resolve elsewhere.
+struct Member {
+    symbols: Vec<Result<u64, String>>,
+    fixups: Vec<Fixup>,
+    little: bool,
+}
+
+#[derive(Default)]
+struct Layout {
+    next: u64,
+    segments: Vec<Segment>,
+    functions: Vec<FunctionSymbol>,
+    /// Global definitions across all members; the first one wins, as with
+    /// a linker pulling members in order.
+    globals: HashMap<String, u64>,
+    mapping_symbols: Vec<MappingSymbol>,
+    /// Placed sections for the metadata diff, as `member:name` in archives.
+    sections: Vec<Section>,
+    arch: Option<(Architecture, u8, Endianness, Abi)>,
+}
+
+impl Layout {
+    /// Give a section the next free address, aligned to at least 16 bytes.
+    fn place(&mut self, mut segment: Segment, align: u64, flags: String) -> u64 {
+        let align = align.clamp(16, 4096);
+        let addr = self.next.div_ceil(align) * align;
+        segment.vaddr = addr;
+        self.next = addr + segment.vsize;
+        let name = match &segment.member { Some(m) => format!("{}:{}", m, segment.name), None => segment.name.clone() };
+        self.sections.push(Section { name, addr, size: segment.vsize, flags });
+        self.segments.push(segment);
+        addr
+    }
+
+    fn elf(&mut self, obj: &elf::Elf, bytes: &[u8], base: usize, member: Option<&str>) -> Member {
+        let arch = Architecture::from_elf_machine(obj.header.e_machine, obj.is_64);
+        self.arch.get_or_insert((
+            arch,
+            if obj.is_64 { 64 } else { 32 },
+            if obj.little_endian { Endianness::Little } else { Endianness::Big },
+            Abi::from_elf(arch, obj.is_64, obj.header.e_flags),
+        ));
+        use elf::section_header::{SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS};
+        let mut sections = vec![None; obj.section_headers.len()];
+        for (i, sh) in obj.section_headers.iter().enumerate() {
+            if sh.sh_flags & SHF_ALLOC as u64 == 0 || sh.sh_size == 0 { continue; }
+            let name = obj.shdr_strtab.get_at(sh.sh_name).unwrap_or("");
+            sections[i] = Some(self.place(Segment {
+                name: name.to_string(),
+                vaddr: 0,
+                vsize: sh.sh_size,
+                offset: (base as u64).saturating_add(sh.sh_offset),
+                file_size: if sh.sh_type == SHT_NOBITS { 0 } else { sh.sh_size },
+                read: true,
+                write: sh.sh_flags & SHF_WRITE as u64 != 0,
+                exec: sh.sh_flags & SHF_EXECINSTR as u64 != 0,
+                member: member.map(str::to_string),
+            }, sh.sh_addralign, metadata::elf_section_flags(sh.sh_flags)));
+        }
+
+        let mut symbols = Vec::with_capacity(obj.syms.len());
+        for sym in obj.syms.iter() {
+            let name = obj.strtab.get_at(sym.st_name).unwrap_or("");
+            let Some(section) = sections.get(sym.st_shndx).copied().flatten() else {
+                symbols.push(Err(name.to_string()));
+                continue;
+            };
+            let addr = section + sym.st_value;
+            symbols.push(Ok(addr));
+            if obj.header.e_machine == elf::header::EM_ARM {
+                // `$a`/`$t`/`$d` mark instruction sets and literal pools;
+                // a function symbol's Thumb bit implies one.
+                let kind = match name.split('.').next() {
+                    Some("$a") => Some(MappingKind::Arm),
+                    Some("$t") => Some(MappingKind::Thumb),
+                    Some("$d") => Some(MappingKind::Data),
+                    _ if sym.st_type() == elf::sym::STT_FUNC => Some(if addr & 1 != 0 { MappingKind::Thumb } else { MappingKind::Arm }),
+                    _ => None,
+                };
+                if let Some(kind) = kind {
+                    self.mapping_symbols.push(MappingSymbol { addr: addr & !1, kind });
+                }
+            }
+            if sym.st_bind() != elf::sym::STB_LOCAL && !name.is_empty() {
+                self.globals.entry(name.to_string()).or_insert(addr);
+            }
+            if sym.st_type() == elf::sym::STT_FUNC && !name.is_empty() {
+                // Bit 0 of an ARM function symbol selects Thumb.
+                let start = if obj.header.e_machine == elf::header::EM_ARM { addr & !1 } else { addr };
+                self.functions.push(FunctionSymbol { name: name.to_string(), start, size: sym.st_size, fragments: Vec::new(), debug: None, demangled: None });
+            }
+        }
+
+        let mut fixups = Vec::new();
+        for (idx, relocs) in &obj.shdr_relocs {
+            let Some(target) = obj.section_headers.get(*idx).map(|sh| sh.sh_info as usize) else { continue };
+            let (Some(Some(section)), Some(sh)) = (sections.get(target), obj.section_headers.get(target)) else { continue };
+            for r in relocs.iter() {
+                let Some(patch) = elf_patch(obj.header.e_machine, r.r_type) else { continue };
+                // A relocation outside its section is corrupt; leave the bytes alone.
+                let Some(file) = fixup_offset(base, bytes.len(), sh.sh_offset, sh.sh_size, r.r_offset, patch.width()) else { continue };
+                fixups.push(Fixup {
+                    file,
+                    place: section + r.r_offset,
+                    sym: r.r_sym,
+                    addend: r.r_addend,
+                    bias: 0,
+                    patch,
+                });
+            }
+        }
+        Member { symbols, fixups, little: obj.little_endian }
+    }
+
+    fn coff(&mut self, obj: &pe::Coff, bytes: &[u8], base: usize, member: Option<&str>) -> Member {
+        use pe::section_table::*;
+        let machine = obj.header.machine;
+        let arch = Architecture::from_coff_machine(machine);
+        // No optional header to say; the machine decides.
+        self.arch.get_or_insert((arch, super::raw::layout(arch).0, Endianness::Little, Abi::Windows));
+        let mut sections = vec![None; obj.sections.len()];
+        for (i, s) in obj.sections.iter().enumerate() {
+            let skip = IMAGE_SCN_LNK_REMOVE | IMAGE_SCN_LNK_INFO | IMAGE_SCN_MEM_DISCARDABLE;
+            if s.characteristics & skip != 0 || s.size_of_raw_data == 0 { continue; }
+            let bss = s.characteristics & IMAGE_SCN_CNT_UNINITIALIZED_DATA != 0;
+            // IMAGE_SCN_ALIGN_* is log2(alignment) + 1 in bits 20..24.
+            let align = match (s.characteristics >> 20) & 0xf { 0 => 16, n => 1u64 << (n - 1) };
+            sections[i] = Some(self.place(Segment {
+                name: s.name().unwrap_or("").to_string(),
+                vaddr: 0,
+                vsize: s.size_of_raw_data as u64,
+                offset: (base as u64).saturating_add(s.pointer_to_raw_data as u64),
+                file_size: if bss { 0 } else { s.size_of_raw_data as u64 },
+                read: true,
+                write: s.characteristics & IMAGE_SCN_MEM_WRITE != 0,
+                exec: s.characteristics & IMAGE_SCN_MEM_EXECUTE != 0,
+                member: member.map(str::to_string),
+            }, align, metadata::coff_section_flags(s.characteristics)));
+        }
+
+        let mut symbols = Vec::new();
+        if let Some(table) = &obj.symbols {
+            for (index, inline_name, sym) in table.iter() {
+                let name = match (inline_name, &obj.strings) {
+                    (Some(n), _) => n.to_string(),
+                    (None, Some(strings)) => sym.name(strings).unwrap_or("").to_string(),
+                    (None, None) => String::new(),
+                };
+                symbols.resize(index, Err(String::new()));
+                let section = (sym.section_number > 0).then(|| sections.get(sym.section_number as usize - 1).copied().flatten()).flatten();
+                let Some(section) = section else {
+                    symbols.push(Err(name));
+                    continue;
+                };
+                let addr = section + sym.value as u64;
+                symbols.push(Ok(addr));
+                const EXTERNAL: u8 = pe::symbol::IMAGE_SYM_CLASS_EXTERNAL;
+                if sym.storage_class == EXTERNAL && !name.is_empty() {
+                    self.globals.entry(name.clone()).or_insert(addr);
+                }
+                // MSVC marks functions with type 0x20 (DT_FCN << 4).
+                if sym.typ >> 4 == 2 && !name.is_empty() {
+                    let size = match (sym.storage_class, sym.number_of_aux_symbols) {
+                        (EXTERNAL, 1..) => table.aux_function_definition(index + 1).map_or(0, |aux| aux.total_size as u64),
+                        _ => 0,
+                    };
+                    self.functions.push(FunctionSymbol { name, start: addr, size, fragments: Vec::new(), debug: None, demangled: None });
+                }
+            }
+        }
+
+        let mut fixups = Vec::new();
+        for (i, s) in obj.sections.iter().enumerate() {
+            let Some(section) = sections[i] else { continue };
+            let Ok(relocs) = s.relocations(bytes) else { continue };
+            for r in relocs {
+                let Some((patch, bias)) = coff_patch(machine, r.typ) else { continue };
+                let offset = r.virtual_address.wrapping_sub(s.virtual_address) as u64;
+                let size = s.size_of_raw_data as u64;
+                let Some(file) = fixup_offset(base, bytes.len(), s.pointer_to_raw_data as u64, size, offset, patch.width()) else { continue };
+                fixups.push(Fixup { file, place: section + offset, sym: r.symbol_table_index as usize, addend: None, bias, patch });
+            }
+        }
+        Member { symbols, fixups, little: true }
+    }
+}
+
+pub fn load(path: &Path, mut data: Vec<u8>) -> Result<BinaryImage> {
+    let mut layout = Layout { next: LAYOUT_BASE, ..Default::default() };
+    let mut members = Vec::new();
+    match Object::parse(&data)? {
+        Object::Archive(ar) => {
+            for i in 0..ar.len() {
+                let Some(m) = ar.get_at(i) else { continue };
+                let base = m.offset as usize;
+                let Some(bytes) = data.get(base..base.saturating_add(m.size())) else { continue };
+                // Symbol indexes, import stubs and anything else that is not an object are skipped.
+                match Object::parse(bytes) {
+                    Ok(Object::Elf(obj)) if obj.header.e_type == elf::header::ET_REL => {
+                        members.push(layout.elf(&obj, bytes, base, Some(m.extended_name())));
+                    }
+                    Ok(Object::COFF(obj)) => members.push(layout.coff(&obj, bytes, base, Some(m.extended_name()))),
+                    _ => {}
+                }
+            }
+        }
+        Object::Elf(obj) => members.push(layout.elf(&obj, &data, 0, None)),
+        Object::COFF(obj) => members.push(layout.coff(&obj, &data, 0, None)),
+        other => bail!("Not a relocatable object: {:?}", other),
+    }
+    let Some((arch, bits, endianness, abi)) = layout.arch else { bail!("{}: archive holds no ELF or COFF objects", path.display()) };
+
+    // Resolve references: the member's own symbols, then global definitions
+    // from any member, then a shared slot per undefined name.
+    let extern_base = layout.next.div_ceil(4096) * 4096;
+    let mut externs: HashMap<String, u64> = HashMap::new();
+    for m in &members {
+        for f in &m.fixups {
+            let target = match m.symbols.get(f.sym) {
+                Some(Ok(addr)) => *addr,
+                Some(Err(name)) if !name.is_empty() => match layout.globals.get(name) {
+                    Some(&addr) => addr,
+                    None => {
+                        let next = extern_base + externs.len() as u64 * EXTERN_SLOT;
+                        *externs.entry(name.clone()).or_insert(next)
+                    }
+                },
+                _ => continue,
+            };
+            let Some(bytes) = data.get_mut(f.file..f.file + f.patch.width()) else { continue };
+            let addend = f.addend.unwrap_or_else(|| f.patch.implicit_addend(bytes, m.little)) + f.bias;
+            f.patch.write(bytes, target.wrapping_add_signed(addend), f.place, m.little);
+        }
+    }
+    if !externs.is_empty() {
+        layout.segments.push(Segment {
+            name: "extern".to_string(),
+            vaddr: extern_base,
+            vsize: externs.len() as u64 * EXTERN_SLOT,
+            offset: 0,
+            file_size: 0,
+            read: true,
+            write: false,
+            exec: false,
+            member: None,
+        });
+    }
+
+    layout.mapping_symbols.sort_by_key(|m| (m.addr, m.kind == MappingKind::Data));
+    layout.mapping_symbols.dedup_by_key(|m| m.addr);
+    // Kernel modules keep their patch tables in sections of their own.
+    let tables: Vec<_> = layout.segments.iter()
+        .filter_map(|s| Table::from_section(&s.name).map(|t| (t, s.vaddr, s.vaddr + s.vsize)))
+        .collect();
+    let mut bin = BinaryImage {
+        path: path.display().to_string(),
+        arch,
+        bits,
+        endianness,
+        abi,
+        image_base: LAYOUT_BASE,
+        segments: layout.segments,
+        functions: layout.functions,
+        mapping_symbols: layout.mapping_symbols,
+        patch_sites: Vec::new(),
+        // Calls to undefined symbols land on their `extern` slot.
+        imports: externs.into_iter().map(|(name, addr)| (addr, name)).collect::<BTreeMap<_, _>>(),
+        metadata: Metadata { sections: layout.sections, ..Default::default() },
+        data: data.into(),
+    };
+    bin.patch_sites = kernel::patch_sites(&bin, &tables);
+    Ok(bin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{Disassembler, Flow};
+    use elf::header::{EM_AARCH64, EM_ARM, EM_RISCV};
+    use elf::reloc::*;
+
+    /// Where the single `.text` section lands, and the first `extern` slot.
+    const TEXT: u64 = LAYOUT_BASE;
+    const EXTERN: u64 = LAYOUT_BASE + 0x1000;
+
+    /// Symbols of the test objects: `(name, st_info, .text offset)`, or
+    /// undefined without an offset.
+    type Sym<'a> = (&'a str, u8, Option<u64>);
+
+    const FUNC: u8 = elf::sym::STT_FUNC;
+    const GLOBAL: u8 = elf::sym::STB_GLOBAL << 4;
+
+    /// A little-endian `ET_REL` object with `.text`, its symbols and one
+    /// relocation section (`.rela.text` or, for ARM, `.rel.text`):
+    /// `(offset, type, symbol index, addend)`.
+    fn elf_object(machine: u16, is_64: bool, text: &[u8], syms: &[Sym], relocs: &[(u64, u32, u32, i64)]) -> Vec<u8> {
+        let rela = machine != EM_ARM;
+        let put = |out: &mut Vec<u8>, v: u64, n: usize| out.extend(&v.to_le_bytes()[..n]);
+        let word = if is_64 { 8 } else { 4 };
+
+        let mut strtab = vec![0u8];
+        let mut symtab = vec![0u8; if is_64 { 24 } else { 16 }];
+        for (name, info, value) in syms {
+            let st_name = strtab.len() as u64;
+            strtab.extend(name.bytes().chain([0]));
+            let shndx = if value.is_some() { 1 } else { 0 };
+            if is_64 {
+                put(&mut symtab, st_name, 4);
+                symtab.extend([*info, 0]);
+                put(&mut symtab, shndx, 2);
+                put(&mut symtab, value.unwrap_or(0), 8);
+                put(&mut symtab, 0, 8);
+            } else {
+                put(&mut symtab, st_name, 4);
+                put(&mut symtab, value.unwrap_or(0), 4);
+                put(&mut symtab, 0, 4);
+                symtab.extend([*info, 0]);
+                put(&mut symtab, shndx, 2);
+            }
+        }
+        let mut reltab = Vec::new();
+        for &(offset, r_type, sym, addend) in relocs {
+            put(&mut reltab, offset, word);
+            let info = if is_64 { (sym as u64) << 32 | r_type as u64 } else { (sym as u64) << 8 | r_type as u64 };
+            put(&mut reltab, info, word);
+            if rela { put(&mut reltab, addend as u64, word); }
+        }
+        let shstrtab = b"\0.text\0.symtab\0.strtab\0.shstrtab\0.rel.text\0.rela.text\0";
+        let (rel_name, rel_type, rel_size) = if rela { (42, 4, 3 * word) } else { (33, 9, 2 * word) };
+
+        let ehsize = if is_64 { 64 } else { 52 };
+        let mut body = Vec::new();
+        let mut place = |data: &[u8]| {
+            let offset = ehsize + body.len() as u64;
+            body.extend(data);
+            body.resize(body.len().div_ceil(8) * 8, 0);
+            (offset, data.len() as u64)
+        };
+        // (name, type, flags, (offset, size), link, info, align, entsize)
+        let sections = [
+            (0, 0, 0, (0, 0), 0, 0, 0, 0),
+            (1, 1, 6, place(text), 0, 0, 4, 0),
+            (7, 2, 0, place(&symtab), 3, 1, 8, symtab.len() as u64 / (syms.len() as u64 + 1)),
+            (15, 3, 0, place(&strtab), 0, 0, 1, 0),
+            (23, 3, 0, place(shstrtab), 0, 0, 1, 0),
+            (rel_name, rel_type, 0, place(&reltab), 2, 1, 8, rel_size as u64),
+        ];
+        let shoff = ehsize + body.len() as u64;
+
+        let mut out = vec![0x7f, b'E', b'L', b'F', if is_64 { 2 } else { 1 }, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        put(&mut out, 1, 2); // ET_REL
+        put(&mut out, machine as u64, 2);
+        put(&mut out, 1, 4);
+        put(&mut out, 0, word); // e_entry
+        put(&mut out, 0, word); // e_phoff
+        put(&mut out, shoff, word);
+        put(&mut out, 0, 4);
+        put(&mut out, ehsize, 2);
+        put(&mut out, 0, 2);
+        put(&mut out, 0, 2);
+        put(&mut out, if is_64 { 64 } else { 40 }, 2);
+        put(&mut out, sections.len() as u64, 2);
+        put(&mut out, 4, 2);
+        out.extend(body);
+        for (name, ty, flags, (offset, size), link, info, align, entsize) in sections {
+            put(&mut out, name, 4);
+            put(&mut out, ty, 4);
+            for v in [flags, 0, offset, size] { put(&mut out, v, word); }
+            put(&mut out, link, 4);
+            put(&mut out, info, 4);
+            for v in [align, entsize] { put(&mut out, v, word); }
+        }
+        out
+    }
+
+    fn load_object(bytes: Vec<u8>) -> BinaryImage {
+        load(Path::new("test.o"), bytes).unwrap()
+    }
+
+    fn text_word(bin: &BinaryImage, at: u64) -> u32 {
+        u32::from_le_bytes(bin.read_va(TEXT + at, 4).unwrap().try_into().unwrap())
+    }
+
+    fn flow(dis: &dyn Disassembler, bin: &BinaryImage, at: u64) -> (Flow, usize) {
+        let (insn, len) = dis.decode(bin.read_va(TEXT + at, 8).unwrap(), TEXT + at).unwrap();
+        (insn.flow, len)
+    }
+
+    #[test]
+    fn aarch64_branch_relocations() {
+        // bl ext; b local; nop; nop; local: ret
+        let text = [0x9400_0000u32, 0x1400_0000, 0xd503_201f, 0xd503_201f, 0xd65f_03c0];
+        let text: Vec<u8> = text.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let syms = [("local", FUNC, Some(0x10)), ("ext", GLOBAL, None)];
+        let bin = load_object(elf_object(EM_AARCH64, true, &text, &syms, &[(0, R_AARCH64_CALL26, 2, 0), (4, R_AARCH64_JUMP26, 1, 0)]));
+        assert_eq!(bin.imports.get(&EXTERN).map(String::as_str), Some("ext"));
+        assert_eq!(text_word(&bin, 0), 0x9400_0400);
+        assert_eq!(text_word(&bin, 4), 0x1400_0003);
+        let dis = crate::arch::aarch64::AArch64::new();
+        assert_eq!(flow(&dis, &bin, 0).0, Flow::Call(Some(EXTERN)));
+        assert_eq!(flow(&dis, &bin, 4).0, Flow::Jump(Some(TEXT + 0x10)));
+    }
+
+    #[test]
+    fn arm_branch_relocations() {
+        // bl ext; b arm_fn; bl thumb_fn (becomes blx); nop; arm_fn: bx lr; ...; thumb_fn: bx lr
+        // The addends are in place: -8 for the pipeline.
+        let mut text: Vec<u8> = [0xebff_fffeu32, 0xeaff_fffe, 0xebff_fffe, 0xe320_f000, 0xe12f_ff1e, 0, 0, 0]
+            .iter().flat_map(|w| w.to_le_bytes()).collect();
+        text.extend([0x70, 0x47]);
+        let syms = [("arm_fn", FUNC, Some(0x10)), ("thumb_fn", FUNC, Some(0x21)), ("ext", GLOBAL, None)];
+        let relocs = [(0, R_ARM_CALL, 3, 0), (4, R_ARM_JUMP24, 1, 0), (8, R_ARM_CALL, 2, 0)];
+        let bin = load_object(elf_object(EM_ARM, false, &text, &syms, &relocs));
+        assert_eq!(text_word(&bin, 0), 0xeb00_03fe);
+        assert_eq!(text_word(&bin, 4), 0xea00_0001);
+        assert_eq!(text_word(&bin, 8), 0xfa00_0004);
+        let dis = crate::arch::arm::Arm::new(&bin);
+        assert_eq!(flow(&dis, &bin, 0).0, Flow::Call(Some(EXTERN)));
+        assert_eq!(flow(&dis, &bin, 4).0, Flow::Jump(Some(TEXT + 0x10)));
+        assert_eq!(flow(&dis, &bin, 8).0, Flow::Call(Some(TEXT + 0x20)));
+    }
+
+    #[test]
+    fn thumb_branch_relocations() {
+        // $t: bl ext (becomes blx); b.w thumb_fn; bl thumb_fn; ...; thumb_fn: bx lr
+        // The addends are in place: -4 for the pipeline.
+        let mut text: Vec<u8> = [0xf7ff_u16, 0xfffe, 0xf7ff, 0xbffe, 0xf7ff, 0xfffe].iter().flat_map(|h| h.to_le_bytes()).collect();
+        text.resize(0x20, 0);
+        text.extend([0x70, 0x47]);
+        let syms = [("$t", 0, Some(0)), ("thumb_fn", FUNC, Some(0x21)), ("ext", GLOBAL, None)];
+        let relocs = [(0, R_ARM_THM_PC22, 3, 0), (4, R_ARM_THM_JUMP24, 2, 0), (8, R_ARM_THM_PC22, 2, 0)];
+        let bin = load_object(elf_object(EM_ARM, false, &text, &syms, &relocs));
+        let halves = |at| { let w = text_word(&bin, at); (w as u16, (w >> 16) as u16) };
+        assert_eq!(halves(0), (0xf000, 0xeffe)); // blx, from the word-aligned PC
+        assert_eq!(halves(4), (0xf000, 0xb80c));
+        assert_eq!(halves(8), (0xf000, 0xf80a));
+        let dis = crate::arch::arm::Arm::new(&bin);
+        assert_eq!(flow(&dis, &bin, 0), (Flow::Call(Some(EXTERN)), 4));
+        assert_eq!(flow(&dis, &bin, 4), (Flow::Jump(Some(TEXT + 0x20)), 4));
+        assert_eq!(flow(&dis, &bin, 8), (Flow::Call(Some(TEXT + 0x20)), 4));
+    }
+
+    #[test]
+    fn riscv_call_relocations() {
+        // call ext (auipc ra, 0; jalr ra); tail local (auipc t1, 0; jalr zero, t1); ...; local: ret
+        let mut text: Vec<u8> = [0x0000_0097u32, 0x0000_80e7, 0x0000_0317, 0x0003_0067].iter().flat_map(|w| w.to_le_bytes()).collect();
+        text.resize(0x20, 0);
+        text.extend(0x0000_8067u32.to_le_bytes());
+        let syms = [("local", FUNC, Some(0x20)), ("ext", GLOBAL, None)];
+        let relocs = [(0, R_RISCV_CALL_PLT, 2, 8), (8, R_RISCV_CALL, 1, 0)];
+        let bin = load_object(elf_object(EM_RISCV, true, &text, &syms, &relocs));
+        // ext + 8 is 0x1008 past `P`: the `auipc` part rounds to 0x1000.
+        assert_eq!((text_word(&bin, 0), text_word(&bin, 4)), (0x0000_1097, 0x0080_80e7));
+        assert_eq!((text_word(&bin, 8), text_word(&bin, 12)), (0x0000_0317, 0x0183_0067));
+        let dis = crate::arch::riscv::RiscV::new(true);
+        assert_eq!(flow(&dis, &bin, 0), (Flow::Call(Some(EXTERN + 8)), 8));
+        assert_eq!(flow(&dis, &bin, 8), (Flow::Jump(Some(TEXT + 0x20)), 8));
+    }
+
+    #[test]
+    fn relocations_outside_their_section_are_skipped() {
+        let text: Vec<u8> = [0x9400_0000u32, 0x9400_0000].iter().flat_map(|w| w.to_le_bytes()).collect();
+        let syms = [("ext", GLOBAL, None)];
+        let relocs = [(6, R_AARCH64_CALL26, 1, 0), (u64::MAX - 1, R_AARCH64_CALL26, 1, 0), (0x1000, R_AARCH64_CALL26, 1, 0)];
+        let bin = load_object(elf_object(EM_AARCH64, true, &text, &syms, &relocs));
+        assert_eq!((text_word(&bin, 0), text_word(&bin, 4)), (0x9400_0000, 0x9400_0000));
+    }
+
+    /// An x86-64 COFF object with `.text` and symbols `ext` (undefined) and
+    /// `local` (at `.text + 0x10`); `relocs` are `(offset, type, symbol)`.
+    fn coff_object(text: &[u8], relocs: &[(u32, u16, u32)]) -> Vec<u8> {
+        let put = |out: &mut Vec<u8>, v: u64, n: usize| out.extend(&v.to_le_bytes()[..n]);
+        let (text_at, relocs_at) = (20 + 40, 20 + 40 + text.len() as u64);
+        let symtab_at = relocs_at + 10 * relocs.len() as u64;
+        let mut out = Vec::new();
+        for (v, n) in [(pe::header::COFF_MACHINE_X86_64 as u64, 2), (1, 2), (0, 4), (symtab_at, 4), (2, 4), (0, 2), (0, 2)] {
+            put(&mut out, v, n);
+        }
+        out.extend(b".text\0\0\0");
+        for (v, n) in [(0, 4), (0, 4), (text.len() as u64, 4), (text_at, 4), (relocs_at, 4), (0, 4), (relocs.len() as u64, 2), (0, 2)] {
+            put(&mut out, v, n);
+        }
+        put(&mut out, 0x6050_0020, 4); // code, 16-byte aligned, read/execute
+        out.extend(text);
+        for &(offset, typ, sym) in relocs {
+            put(&mut out, offset as u64, 4);
+            put(&mut out, sym as u64, 4);
+            put(&mut out, typ as u64, 2);
+        }
+        // (name, value, section, type, class)
+        for (name, value, section, typ) in [(b"ext\0\0\0\0\0", 0, 0, 0x20), (b"local\0\0\0", 0x10, 1, 0x20)] {
+            out.extend(name);
+            put(&mut out, value, 4);
+            put(&mut out, section, 2);
+            put(&mut out, typ, 2);
+            out.extend([pe::symbol::IMAGE_SYM_CLASS_EXTERNAL, 0]);
+        }
+        put(&mut out, 4, 4); // empty string table
+        out
+    }
+
+    #[test]
+    fn coff_rel32_relocations() {
+        use pe::relocation::{IMAGE_REL_AMD64_REL32, IMAGE_REL_AMD64_REL32_4};
+        // call ext; jmp local; cmp dword [rip + local], 1; ...; local: ret
+        let mut text = vec![0xe8, 0, 0, 0, 0, 0xe9, 0, 0, 0, 0];
+        text.extend([0x83, 0x3d, 0, 0, 0, 0, 0x01]);
+        text.resize(0x10, 0xcc);
+        text.push(0xc3);
+        let relocs = [(1, IMAGE_REL_AMD64_REL32, 0), (6, IMAGE_REL_AMD64_REL32, 1), (12, IMAGE_REL_AMD64_REL32_4 - 3, 1)];
+        let bin = load_object(coff_object(&text, &relocs));
+        assert_eq!(bin.imports.get(&EXTERN).map(String::as_str), Some("ext"));
+        assert_eq!(bin.read_va(TEXT + 1, 4), Some(&0x0ffbu32.to_le_bytes()[..]));
+        assert_eq!(bin.read_va(TEXT + 6, 4), Some(&6u32.to_le_bytes()[..]));
+        // REL32_1: relative to the end of the instruction, one byte past the field.
+        assert_eq!(bin.read_va(TEXT + 12, 4), Some(&(-1i32).to_le_bytes()[..]));
+        let dis = crate::arch::x86_64::X86_64::new();
+        assert_eq!(flow(&dis, &bin, 0).0, Flow::Call(Some(EXTERN)));
+        assert_eq!(flow(&dis, &bin, 5).0, Flow::Jump(Some(TEXT + 0x10)));
+    }
+
+    #[test]
+    fn object_metadata() {
+        let text = Section { name: ".text".to_string(), addr: TEXT, size: 8, flags: "AX".to_string() };
+        let a = load_object(elf_object(EM_AARCH64, true, &[0; 8], &[], &[]));
+        assert_eq!(a.metadata.sections, [text]);
+        let coff = load_object(coff_object(&[0xc3; 8], &[]));
+        assert_eq!(coff.metadata.sections[0].flags, "r-x");
+
+        let b = load_object(elf_object(EM_RISCV, false, &[0; 16], &[], &[]));
+        let diff = crate::diff::metadata::diff_metadata(&a, &b);
+        let headers: Vec<_> = diff.headers.iter().map(|c| (c.name.as_str(), c.old.as_deref(), c.new.as_deref())).collect();
+        assert_eq!(headers, [("arch", Some("aarch64"), Some("riscv32")), ("bits", Some("64"), Some("32"))]);
+        assert_eq!((diff.sections[0].old_size, diff.sections[0].new_size), (Some(8), Some(16)));
+    }
+}
@@ -0,0 +1,116 @@
+//! Function symbols from a PDB, matched to a PE image by its CodeView record.
+
+use super::LoadOptions;
+use anyhow::Result;
+use goblin::pe;
+use pdb::{FallibleIterator, PDB, SymbolData};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Identity of the PDB an image was linked against (CodeView `RSDS` record).
+pub struct PdbId {
+    /// GUID bytes in their on-disk (mixed-endian) layout.
+    pub guid: [u8; 16],
+    pub age: u32,
+    /// Path the linker wrote the PDB to.
+    pub path: String,
+}
+
+impl PdbId {
+    pub fn from_pe(pe: &pe::PE) -> Option<Self> {
+        let cv = pe.debug_data.as_ref()?.codeview_pdb70_debug_info.as_ref()?;
+        let path = String::from_utf8_lossy(cv.filename).trim_end_matches('\0').to_string();
+        Some(Self { guid: cv.signature, age: cv.age, path })
+    }
+
+    /// Directory name a symbol store files this PDB under: the GUID as
+    /// uppercase hex (first three fields byte-swapped) followed by the age.
+    pub fn symstore_key(&self) -> String {
+        let g = &self.guid;
+        let mut key = format!(
+            "{:08X}{:04X}{:04X}",
+            u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+            u16::from_le_bytes([g[4], g[5]]),
+            u16::from_le_bytes([g[6], g[7]]),
+        );
+        for b in &g[8..] {
+            key.push_str(&format!("{:02X}", b));
+        }
+        key.push_str(&format!("{:X}", self.age));
+        key
+    }
+
+    /// File name part of the recorded path, which may use either separator.
+    fn file_name(&self) -> &str {
+        self.path.rsplit(['\\', '/']).next().unwrap_or(&self.path)
+    }
+}
+
+/// A function from the PDB, by RVA.
+pub struct PdbFunction {
+    pub name: String,
+    pub rva: u32,
+    /// Zero for public symbols without a matching procedure.
+    pub size: u32,
+}
+
+/// Places to look for the PDB, most specific first: explicit `--pdb` files,
+/// symbol directories (flat and symbol-store layouts), the path recorded by
+/// the linker, and the image's own directory.
+fn candidates(image: &Path, id: &PdbId, opts: &LoadOptions) -> Vec<PathBuf> {
+    let name = id.file_name();
+    let mut out = opts.pdb.clone();
+    for dir in &opts.symbol_paths {
+        out.push(dir.join(name));
+        out.push(dir.join(name).join(id.symstore_key()).join(name));
+    }
+    out.push(PathBuf::from(&id.path));
+    if let Some(dir) = image.parent() {
+        out.push(dir.join(name));
+    }
+    out
+}
+
+fn matches(path: &Path, id: &PdbId) -> bool {
+    let Ok(file) = File::open(path) else { return false };
+    let Ok(mut pdb) = PDB::open(file) else { return false };
+    let Ok(info) = pdb.pdb_information() else { return false };
+    // The DBI stream carries the age the linker stamps into the image.
+    let age = pdb.debug_information().ok().and_then(|dbi| dbi.age()).unwrap_or(info.age);
+    info.guid.to_bytes_le() == id.guid && age == id.age
+}
+
+/// The first candidate PDB whose GUID and age match the image.
+pub fn find(image: &Path, id: &PdbId, opts: &LoadOptions) -> Option<PathBuf> {
+    candidates(image, id, opts).into_iter().find(|p| p.is_file() && matches(p, id))
+}
+
+/// Procedures from every module (private symbols, with sizes), followed by
+/// public function symbols.
+pub fn functions(path: &Path) -> Result<Vec<PdbFunction>> {
+    let mut pdb = PDB::open(File::open(path)?)?;
+    let address_map = pdb.address_map()?;
+    let mut out = Vec::new();
+
+    let dbi = pdb.debug_information()?;
+    let mut modules = dbi.modules()?;
+    while let Some(module) = modules.next()? {
+        let Some(info) = pdb.module_info(&module)? else { continue };
+        let mut symbols = info.symbols()?;
+        while let Some(sym) = symbols.next()? {
+            let Ok(SymbolData::Procedure(proc)) = sym.parse() else { continue };
+            let Some(rva) = proc.offset.to_rva(&address_map) else { continue };
+            out.push(PdbFunction { name: proc.name.to_string().into_owned(), rva: rva.0, size: proc.len });
+        }
+    }
+
+    let globals = pdb.global_symbols()?;
+    let mut symbols = globals.iter();
+    while let Some(sym) = symbols.next()? {
+        let Ok(SymbolData::Public(public)) = sym.parse() else { continue };
+        if !public.function { continue; }
+        let Some(rva) = public.offset.to_rva(&address_map) else { continue };
+        out.push(PdbFunction { name: public.name.to_string().into_owned(), rva: rva.0, size: 0 });
+    }
+    Ok(out)
+}
@@ -0,0 +1,245 @@
+//! Flat images with no container format, such as firmware and ROM dumps,
+//! mapped at a base address the caller supplies.
+
+use super::metadata::{self, Metadata, Section};
+use super::{BinaryImage, FunctionSymbol, ImageData, MappingKind, MappingSymbol, Segment, merge_symbols, synthetic_name};
+use crate::arch::{Abi, Architecture, Endianness};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Layout of a raw image.
+#[derive(Debug, Clone, Default)]
+pub struct RawOptions {
+    /// Address the first byte of the file is loaded at.
+    pub base: u64,
+    /// Known code addresses; the base address when empty. On ARM, bit 0
+    /// selects Thumb.
+    pub entry_points: Vec<u64>,
+    /// CSV, `nm` output or GNU linker map naming functions in the image.
+    pub symbols: Option<PathBuf>,
+    /// Byte order, for bi-endian architectures (`mipsel`, `ppc64le`); the
+    /// architecture's usual one when unset.
+    pub endianness: Option<Endianness>,
+}
+
+/// Parse a hexadecimal address, with or without a `0x` prefix.
+pub fn parse_addr(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    u64::from_str_radix(digits, 16).ok()
+}
+
+/// Function symbols from a text symbol list, one per line:
+///
+/// - CSV: `name,address[,size]` or `address,name[,size]`; header lines are
+///   skipped because neither column parses as an address
+/// - `nm` output: `address type name`, keeping text (`t`/`T`/`w`/`W`) symbols
+/// - GNU ld map files: the `address name` lines of the memory map
+///
+/// Addresses keep their Thumb bit.
+pub fn parse_symbols(text: &str) -> Vec<FunctionSymbol> {
+    let mut out = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let (name, start, size) = if line.contains(',') {
+            let cols: Vec<&str> = line.split(',').map(|c| c.trim().trim_matches('"')).collect();
+            if cols.len() < 2 { continue; }
+            let size = cols.get(2).and_then(|s| parse_addr(s)).unwrap_or(0);
+            // Names like `add` or `cafe` are valid hex too; a `0x` prefix settles it.
+            let addr_first = match (parse_addr(cols[0]), parse_addr(cols[1])) {
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(_), Some(_)) => cols[0].starts_with("0x") && !cols[1].starts_with("0x"),
+                (None, None) => continue,
+            };
+            let (name, addr) = if addr_first { (cols[1], cols[0]) } else { (cols[0], cols[1]) };
+            (name, parse_addr(addr).unwrap(), size)
+        } else {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            match cols[..] {
+                [addr, name] if addr.starts_with("0x") => match parse_addr(addr) {
+                    Some(addr) => (name, addr, 0),
+                    None => continue,
+                },
+                [addr, "t" | "T" | "w" | "W", name] => match parse_addr(addr) {
+                    Some(addr) => (name, addr, 0),
+                    None => continue,
+                },
+                _ => continue,
+            }
+        };
+        if name.is_empty() { continue; }
+        out.push(FunctionSymbol { name: name.to_string(), start, size, fragments: Vec::new(), debug: None, demangled: None });
+    }
+    out
+}
+
+/// Pointer width and default byte order of `arch`; big-endian for the
+/// architectures that are mostly run that way.
+pub(super) fn layout(arch: Architecture) -> (u8, Endianness) {
+    use Architecture::*;
+    match arch {
+        X86 | Arm | RiscV32 => (32, Endianness::Little),
+        Mips | PowerPc | Sparc => (32, Endianness::Big),
+        Mips64 | PowerPc64 | Sparc64 | S390x => (64, Endianness::Big),
+        X86_64 | AArch64 | RiscV64 | Unknown(_) => (64, Endianness::Little),
+    }
+}
+
+/// The whole file as one read/execute region at `raw.base`.
+pub fn load(path: &Path, data: ImageData, arch: Architecture, raw: &RawOptions) -> Result<BinaryImage> {
+    let segment = Segment {
+        name: "RAW".to_string(),
+        vaddr: raw.base,
+        vsize: data.len() as u64,
+        offset: 0,
+        file_size: data.len() as u64,
+        read: true,
+        write: false,
+        exec: true,
+        member: None,
+    };
+    let mut symbols = match &raw.symbols {
+        Some(p) => parse_symbols(&std::fs::read_to_string(p).with_context(|| format!("reading symbols from {}", p.display()))?),
+        None => Vec::new(),
+    };
+    symbols.retain(|s| segment.contains(s.start));
+
+    let entries = if raw.entry_points.is_empty() { vec![raw.base] } else { raw.entry_points.clone() };
+    // Bit 0 of an ARM code address selects Thumb; it is not part of the address.
+    let addr_mask = if arch == Architecture::Arm { !1 } else { !0 };
+    let mut mapping_symbols: Vec<MappingSymbol> = Vec::new();
+    if arch == Architecture::Arm {
+        for addr in entries.iter().copied().chain(symbols.iter().map(|s| s.start)) {
+            let kind = if addr & 1 != 0 { MappingKind::Thumb } else { MappingKind::Arm };
+            mapping_symbols.push(MappingSymbol { addr: addr & !1, kind });
+        }
+        mapping_symbols.sort_by_key(|m| m.addr);
+        mapping_symbols.dedup_by_key(|m| m.addr);
+    }
+
+    let entry = entries.first().map(|&a| a & addr_mask);
+    let mut functions = Vec::new();
+    for s in &mut symbols {
+        s.start &= addr_mask;
+    }
+    merge_symbols(&mut functions, symbols);
+    let seeds = entries.into_iter()
+        .map(|a| a & addr_mask)
+        .filter(|&a| segment.contains(a))
+        .map(|start| FunctionSymbol { name: synthetic_name(start), start, size: 0, fragments: Vec::new(), debug: None, demangled: None })
+        .collect();
+    merge_symbols(&mut functions, seeds);
+
+    let (bits, usual) = layout(arch);
+    let endianness = raw.endianness.unwrap_or(usual);
+    let section = Section {
+        name: segment.name.clone(),
+        addr: segment.vaddr,
+        size: segment.vsize,
+        flags: metadata::rwx(segment.read, segment.write, segment.exec),
+    };
+    Ok(BinaryImage {
+        path: path.display().to_string(),
+        arch,
+        bits,
+        endianness,
+        abi: Abi::SysV,
+        image_base: raw.base,
+        segments: vec![segment],
+        functions,
+        mapping_symbols,
+        patch_sites: Vec::new(),
+        imports: Default::default(),
+        metadata: Metadata { entry, sections: vec![section], ..Default::default() },
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(text: &str) -> Vec<(String, u64, u64)> {
+        parse_symbols(text).into_iter().map(|f| (f.name, f.start, f.size)).collect()
+    }
+
+    #[test]
+    fn csv_symbols() {
+        let text = "name,address,size\nreset,0x8000,0x40\n0x8040,main\n\"irq\", 0x8100 , 10\n";
+        assert_eq!(names(text), [("reset".into(), 0x8000, 0x40), ("main".into(), 0x8040, 0), ("irq".into(), 0x8100, 0x10)]);
+        // Both columns are hex: the `0x` prefix says which is the address.
+        assert_eq!(names("cafe,0x1000\n0x2000,add\n"), [("cafe".into(), 0x1000, 0), ("add".into(), 0x2000, 0)]);
+        // No address, no name, or a single column.
+        assert!(names("reset,start\n0x8000,\nreset,\n").is_empty());
+    }
+
+    #[test]
+    fn nm_symbols() {
+        let text = "00008000 T reset\n00008041 t thumb_helper\n00009000 W weak_fn\n0000a000 D data\n         U undefined\n";
+        assert_eq!(names(text), [("reset".into(), 0x8000, 0), ("thumb_helper".into(), 0x8041, 0), ("weak_fn".into(), 0x9000, 0)]);
+        assert!(names("zzzz T bad_address\n00008000 T\n").is_empty());
+    }
+
+    #[test]
+    fn ld_map_symbols() {
+        let text = "\
+Memory Configuration
+
+Name             Origin             Length
+FLASH            0x0000000008000000 0x0000000000100000
+
+.text           0x0000000008000000      0x1a4
+ .text          0x0000000008000000       0x40 startup.o
+                0x0000000008000000                Reset_Handler
+                0x0000000008000040                main
+                0x0000000008000100                . = ALIGN (0x4)
+";
+        let funs = names(text);
+        assert!(funs.contains(&("Reset_Handler".into(), 0x0800_0000, 0)));
+        assert!(funs.contains(&("main".into(), 0x0800_0040, 0)));
+        // Section and assignment lines have more than two columns.
+        assert_eq!(funs.len(), 2);
+    }
+
+    fn raw(bytes: &[u8], arch: Architecture, opts: &RawOptions) -> BinaryImage {
+        load(Path::new("fw.bin"), ImageData::from(bytes.to_vec()), arch, opts).unwrap()
+    }
+
+    #[test]
+    fn load_at_base_address() {
+        let opts = RawOptions { base: 0x8000_0000, entry_points: vec![0x8000_0010, 0x9000_0000], ..Default::default() };
+        let bin = raw(&[0; 0x100], Architecture::AArch64, &opts);
+        assert_eq!((bin.image_base, bin.bits, bin.endianness), (0x8000_0000, 64, Endianness::Little));
+        assert_eq!((bin.segments[0].vaddr, bin.segments[0].vsize, bin.segments[0].offset), (0x8000_0000, 0x100, 0));
+        // Entry points outside the image are dropped.
+        let starts: Vec<u64> = bin.functions.iter().map(|f| f.start).collect();
+        assert_eq!(starts, [0x8000_0010]);
+        assert_eq!(bin.read_va(0x8000_00f0, 0x10).map(<[u8]>::len), Some(0x10));
+        assert_eq!(bin.metadata.entry, Some(0x8000_0010));
+        let section = &bin.metadata.sections[0];
+        assert_eq!((section.addr, section.size, section.flags.as_str()), (0x8000_0000, 0x100, "r-x"));
+
+        // Without entry points, code starts at the base.
+        let bin = raw(&[0; 0x10], Architecture::AArch64, &RawOptions { base: 0x1000, ..Default::default() });
+        assert_eq!(bin.functions.iter().map(|f| f.start).collect::<Vec<_>>(), [0x1000]);
+    }
+
+    #[test]
+    fn thumb_entry_points() {
+        let opts = RawOptions { base: 0x1000, entry_points: vec![0x1000, 0x1041], ..Default::default() };
+        let bin = raw(&[0; 0x100], Architecture::Arm, &opts);
+        assert_eq!(bin.functions.iter().map(|f| f.start).collect::<Vec<_>>(), [0x1000, 0x1040]);
+        let modes: Vec<_> = bin.mapping_symbols.iter().map(|m| (m.addr, m.kind)).collect();
+        assert_eq!(modes, [(0x1000, MappingKind::Arm), (0x1040, MappingKind::Thumb)]);
+    }
+
+    #[test]
+    fn byte_order() {
+        assert_eq!(raw(&[0; 4], Architecture::Mips, &RawOptions::default()).endianness, Endianness::Big);
+        let mipsel = RawOptions { endianness: Some(Endianness::Little), ..Default::default() };
+        assert_eq!(raw(&[0; 4], Architecture::Mips, &mipsel).endianness, Endianness::Little);
+        let ppc64le = RawOptions { endianness: Some(Endianness::Little), ..Default::default() };
+        assert_eq!(raw(&[0; 4], Architecture::PowerPc64, &ppc64le).endianness, Endianness::Little);
+    }
+}
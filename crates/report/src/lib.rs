@@ -1,7 +1,7 @@
-use serde::Serialize;
 use anyhow::Result;
-use crate as bindiff_report; // for docs
-use bindiff_core::diff::{DiffResult, FunctionDelta, MatchKind};
+use bindiff_core::diff::{DiffResult, MatchKind, MetadataDiff};
+use bindiff_core::diff::metadata::{FieldChange, SetChange};
+use bindiff_core::loader::DebugInfo;
 
 const HTML_TEMPLATE: &str = r#"
 <!doctype html>
@@ -23,6 +23,15 @@ pre { background: #0b1020; color: #d1d5db; padding: 1rem; border-radius: 8px; ov
 h2 { margin-top: 2rem; }
 .fn { margin-bottom: 1rem; }
 .fn h3 { margin: 0; font-size: 1rem; }
+.src { color: #6b7280; font-size: .875rem; }
+.member { color: #6b7280; font-family: ui-monospace, monospace; font-size: .875rem; }
+table.members { border-collapse: collapse; }
+table.members td, table.members th { border: 1px solid #ddd; padding: .25rem .75rem; text-align: right; }
+table.members td:first-child, table.members th:first-child { text-align: left; }
+table.meta { border-collapse: collapse; margin-bottom: 1rem; }
+table.meta td, table.meta th { border: 1px solid #ddd; padding: .25rem .75rem; text-align: left; font-family: ui-monospace, monospace; }
+.meta .added { color: #1e3a8a; }
+.meta .removed { color: #991b1b; }
 </style>
 </head>
 <body>
@@ -33,7 +42,8 @@ h2 { margin-top: 2rem; }
   <div class='card'><span class='badge blue'>Added</span> {{added}}</div>
   <div class='card'><span class='badge red'>Removed</span> {{removed}}</div>
 </div>
-
+{{members}}
+{{metadata}}
 <h2>Modified</h2>
 {{#each modified_items}}
 <div class='fn card'>
@@ -76,27 +86,126 @@ fn escape_html(s: &str) -> String {
     s.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;")
 }
 
+/// A function name, demangled when possible with the raw symbol as tooltip.
+fn name_html(raw: Option<&str>, demangled: Option<&str>) -> String {
+    match (raw, demangled) {
+        (Some(raw), Some(d)) => format!("<span title='{}'>{}</span>", escape_html(raw).replace('\'', "&#39;"), escape_html(d)),
+        (raw, _) => escape_html(raw.unwrap_or("?")),
+    }
+}
+
+/// `[member]` label for functions from an archive.
+fn member_html(member: Option<&str>) -> String {
+    member.map(|m| format!("<span class='member'>[{}]</span> ", escape_html(m))).unwrap_or_default()
+}
+
+/// Per-member counts table, empty unless the inputs were archives.
+fn members_html(result: &DiffResult) -> String {
+    let members = result.member_summary();
+    if members.is_empty() { return String::new(); }
+    let mut s = String::from("<h2>Archive members</h2>\n<table class='members'><tr><th>Member</th><th>Unchanged</th><th>Modified</th><th>Added</th><th>Removed</th></tr>");
+    for (name, m) in &members {
+        s.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(name), m.unchanged, m.modified, m.added, m.removed
+        ));
+    }
+    s.push_str("</table>\n");
+    s
+}
+
+fn value_html(v: Option<&str>) -> String {
+    v.map(escape_html).unwrap_or_else(|| "—".to_string())
+}
+
+fn fields_html(title: &str, changes: &[FieldChange]) -> String {
+    if changes.is_empty() { return String::new(); }
+    let mut s = format!("<h3>{}</h3>\n<table class='meta'><tr><th></th><th>Old</th><th>New</th></tr>", title);
+    for c in changes {
+        s.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{}</td></tr>", escape_html(&c.name), value_html(c.old.as_deref()), value_html(c.new.as_deref())));
+    }
+    s.push_str("</table>\n");
+    s
+}
+
+fn set_html(title: &str, change: &SetChange) -> String {
+    if change.is_empty() { return String::new(); }
+    let mut s = format!("<h3>{}</h3>\n<ul class='meta'>", title);
+    for a in &change.added {
+        s.push_str(&format!("<li class='added'>+ {}</li>", escape_html(a)));
+    }
+    for r in &change.removed {
+        s.push_str(&format!("<li class='removed'>− {}</li>", escape_html(r)));
+    }
+    s.push_str("</ul>\n");
+    s
+}
+
+/// Header, section, segment, library and hardening changes.
+fn metadata_html(metadata: Option<&MetadataDiff>) -> String {
+    let Some(m) = metadata.filter(|m| !m.is_empty()) else { return String::new() };
+    let mut s = String::from("<h2>Metadata</h2>\n");
+    s.push_str(&fields_html("Headers", &m.headers));
+    s.push_str(&fields_html("Hardening", &m.hardening));
+    if !m.sections.is_empty() {
+        s.push_str("<h3>Sections</h3>\n<table class='meta'><tr><th>Section</th><th>Old size</th><th>New size</th><th>Old flags</th><th>New flags</th></tr>");
+        for c in &m.sections {
+            let size = |v: Option<u64>| v.map(|v| format!("0x{:x}", v));
+            s.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&c.name),
+                value_html(size(c.old_size).as_deref()),
+                value_html(size(c.new_size).as_deref()),
+                value_html(c.old_flags.as_deref()),
+                value_html(c.new_flags.as_deref()),
+            ));
+        }
+        s.push_str("</table>\n");
+    }
+    s.push_str(&fields_html("Segments", &m.segments));
+    s.push_str(&set_html("Libraries", &m.libraries));
+    s.push_str(&set_html("Imports", &m.imports));
+    s
+}
+
+/// `file:line` and inlined callees, for functions that have debug info.
+fn source_html(debug: Option<&DebugInfo>) -> String {
+    let Some(d) = debug else { return String::new() };
+    let mut s = format!(" <span class='src'>{}", escape_html(&d.to_string()));
+    if !d.inlined.is_empty() {
+        s.push_str(&format!(" (inlines {})", escape_html(&d.inlined.join(", "))));
+    }
+    s.push_str("</span>");
+    s
+}
+
 pub fn render_html(result: &DiffResult) -> Result<String> {
-    let summary = format!(
-        "{}|{}|{}|{}",
-        result.unchanged.len(), result.modified.len(), result.added.len(), result.removed.len()
-    );
+    render_html_with(result, None)
+}
+
+/// `render_html` with a metadata section for `metadata`.
+pub fn render_html_with(result: &DiffResult, metadata: Option<&MetadataDiff>) -> Result<String> {
     let mut html = HTML_TEMPLATE.to_string();
     html = html.replace("{{unchanged}}", &result.unchanged.len().to_string());
     html = html.replace("{{modified}}", &result.modified.len().to_string());
     html = html.replace("{{added}}", &result.added.len().to_string());
     html = html.replace("{{removed}}", &result.removed.len().to_string());
+    html = html.replace("{{members}}", &members_html(result));
+    html = html.replace("{{metadata}}", &metadata_html(metadata));
 
     let mut modified_block = String::new();
     for m in &result.modified {
-        let name = format!("{} → {}", m.name_a.clone().unwrap_or("?".into()), m.name_b.clone().unwrap_or("?".into()));
+        let name = format!("{} → {}", name_html(m.name_a.as_deref(), m.demangled_a.as_deref()), name_html(m.name_b.as_deref(), m.demangled_b.as_deref()));
         let ham = match m.kind {
             MatchKind::Fuzzy { hamming } => Some(hamming),
             _ => None,
         };
         let diff = m.unified_diff.as_deref().map(escape_html);
         modified_block.push_str("<div class='fn card'>");
-        modified_block.push_str(&format!("<h3>{}</h3>", escape_html(&name)));
+        modified_block.push_str(&format!("<h3>{}{}</h3>", member_html(m.member()), name));
+        if m.debug_a.is_some() || m.debug_b.is_some() {
+            modified_block.push_str(&format!("<div>{} →{}</div>", source_html(m.debug_a.as_ref()), source_html(m.debug_b.as_ref())));
+        }
         if let Some(h) = ham {
             modified_block.push_str(&format!("<div>SimHash Hamming distance: {}</div>", h));
         }
@@ -113,19 +222,21 @@ pub fn render_html(result: &DiffResult) -> Result<String> {
         1
     );
 
-    let list_block = |items: &Vec<String>| -> String {
+    let list_block = |items: &Vec<(String, Option<&DebugInfo>, Option<&str>)>| -> String {
         let mut s = String::new();
-        for it in items {
+        for (name, debug, member) in items {
             s.push_str("<li class='card fn'>");
-            s.push_str(&escape_html(it));
+            s.push_str(&member_html(*member));
+            s.push_str(name);
+            s.push_str(&source_html(*debug));
             s.push_str("</li>");
         }
         s
     };
 
-    let added_items: Vec<String> = result.added.iter().map(|d| d.name_b.clone().unwrap_or("?".into())).collect();
-    let removed_items: Vec<String> = result.removed.iter().map(|d| d.name_a.clone().unwrap_or("?".into())).collect();
-    let unchanged_items: Vec<String> = result.unchanged.iter().map(|d| d.name_a.clone().unwrap_or("?".into())).collect();
+    let added_items: Vec<_> = result.added.iter().map(|d| (name_html(d.name_b.as_deref(), d.demangled_b.as_deref()), d.debug_b.as_ref(), d.member())).collect();
+    let removed_items: Vec<_> = result.removed.iter().map(|d| (name_html(d.name_a.as_deref(), d.demangled_a.as_deref()), d.debug_a.as_ref(), d.member())).collect();
+    let unchanged_items: Vec<_> = result.unchanged.iter().map(|d| (name_html(d.name_a.as_deref(), d.demangled_a.as_deref()), d.debug_a.as_ref(), d.member())).collect();
 
     html = html.replacen("{{#each added_items}}\n  <li class='card fn'>{{name}}</li>\n{{/each}}", &list_block(&added_items), 1);
     html = html.replacen("{{#each removed_items}}\n  <li class='card fn'>{{name}}</li>\n{{/each}}", &list_block(&removed_items), 1);